@@ -0,0 +1,31 @@
+use clap::Parser;
+use compositor::{LogEncoding, OutputFormat};
+use std::path::PathBuf;
+
+
+/// Compares the banding of each output format for a log encoding.
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct CliArgs {
+
+    /// JSON file with the log encoding parameters; the standard encoding if omitted
+    #[clap(long, parse(from_os_str))]
+    encoding: Option<PathBuf>,
+}
+
+
+fn main() {
+    let args = CliArgs::parse();
+
+    let encoding = args.encoding.map(|x| LogEncoding::from_file(&x)).unwrap_or_default();
+
+    println!("{:<12} {:>12} {:>12} {:>12}", "format", "band", "max error", "rms error");
+    for format in OutputFormat::ALL {
+        let banding = encoding.banding(format);
+        println!(
+            "{:<12} {:>12.6} {:>12.6} {:>12.6}",
+            format!("{:?}", format), banding.band_stops, banding.max_error_stops, banding.rms_error_stops,
+        );
+    }
+    println!("(all values in stops)");
+}
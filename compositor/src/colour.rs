@@ -0,0 +1,136 @@
+use clap::ArgEnum;
+use serde::{Deserialize, Serialize};
+
+
+type Matrix = [[f64; 3]; 3];
+
+
+/// CIE xy coordinates of the primaries and white point of an RGB colour space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Chromaticities {
+    pub red: [f32; 2],
+    pub green: [f32; 2],
+    pub blue: [f32; 2],
+    pub white: [f32; 2],
+}
+
+impl Chromaticities {
+    /// Rec.709 and sRGB primaries with a D65 white point, which EXR assumes when the attribute is missing.
+    pub const REC709: Chromaticities = Chromaticities {
+        red: [0.64, 0.33],
+        green: [0.30, 0.60],
+        blue: [0.15, 0.06],
+        white: [0.3127, 0.3290],
+    };
+
+    pub const REC2020: Chromaticities = Chromaticities {
+        red: [0.708, 0.292],
+        green: [0.170, 0.797],
+        blue: [0.131, 0.046],
+        white: [0.3127, 0.3290],
+    };
+
+    /// ACES AP1 primaries with the ACES white point.
+    pub const ACESCG: Chromaticities = Chromaticities {
+        red: [0.713, 0.293],
+        green: [0.165, 0.830],
+        blue: [0.128, 0.044],
+        white: [0.32168, 0.33767],
+    };
+
+    /// Matrix from linear RGB in this space to CIE XYZ.
+    fn rgb_to_xyz(&self) -> Matrix {
+        let xyz = |[x, y]: [f32; 2]| [x as f64 / y as f64, 1.0, (1.0 - x as f64 - y as f64) / y as f64];
+        let [r, g, b] = [xyz(self.red), xyz(self.green), xyz(self.blue)];
+        let primaries = [[r[0], g[0], b[0]], [r[1], g[1], b[1]], [r[2], g[2], b[2]]];
+
+        // Scale the primaries so that RGB (1, 1, 1) lands on the white point
+        let scale = apply(&invert(&primaries), xyz(self.white));
+        primaries.map(|row| [row[0] * scale[0], row[1] * scale[1], row[2] * scale[2]])
+    }
+
+    /// Matrix from linear RGB in this space to linear RGB in `target`, adapting the white point
+    /// with Bradford if they differ.
+    pub fn conversion(&self, target: &Chromaticities) -> [[f32; 3]; 3] {
+        let mut m = multiply(&invert(&target.rgb_to_xyz()), &bradford(self.white, target.white));
+        m = multiply(&m, &self.rgb_to_xyz());
+        m.map(|row| row.map(|x| x as f32))
+    }
+
+    /// Weights of R, G and B in luminance, the Y row of the XYZ matrix.
+    pub fn luma_weights(&self) -> [f32; 3] {
+        self.rgb_to_xyz()[1].map(|x| x as f32)
+    }
+}
+
+
+/// Colour space the compositor converts every input to before computing luma.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ArgEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkingSpace {
+    #[default]
+    Rec709,
+    Rec2020,
+    Acescg,
+}
+
+impl WorkingSpace {
+    pub fn chromaticities(&self) -> Chromaticities {
+        match self {
+            WorkingSpace::Rec709 => Chromaticities::REC709,
+            WorkingSpace::Rec2020 => Chromaticities::REC2020,
+            WorkingSpace::Acescg => Chromaticities::ACESCG,
+        }
+    }
+}
+
+
+/// Chromatic adaptation from one white point to another in XYZ.
+fn bradford(source: [f32; 2], target: [f32; 2]) -> Matrix {
+    const BRADFORD: Matrix = [
+        [0.8951, 0.2664, -0.1614],
+        [-0.7502, 1.7135, 0.0367],
+        [0.0389, -0.0685, 1.0296],
+    ];
+
+    let xyz = |[x, y]: [f32; 2]| [x as f64 / y as f64, 1.0, (1.0 - x as f64 - y as f64) / y as f64];
+    let cone_source = apply(&BRADFORD, xyz(source));
+    let cone_target = apply(&BRADFORD, xyz(target));
+
+    let mut scale = [[0.0; 3]; 3];
+    for i in 0..3 {
+        scale[i][i] = cone_target[i] / cone_source[i];
+    }
+    multiply(&invert(&BRADFORD), &multiply(&scale, &BRADFORD))
+}
+
+fn apply(m: &Matrix, v: [f64; 3]) -> [f64; 3] {
+    m.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [[0.0; 3]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, x) in row.iter_mut().enumerate() {
+            *x = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+fn invert(m: &Matrix) -> Matrix {
+    let cofactor = |i: usize, j: usize| {
+        let (r0, r1) = ((i + 1) % 3, (i + 2) % 3);
+        let (c0, c1) = ((j + 1) % 3, (j + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    let determinant = (0..3).map(|j| m[0][j] * cofactor(0, j)).sum::<f64>();
+
+    let mut inverse = [[0.0; 3]; 3];
+    for (i, row) in inverse.iter_mut().enumerate() {
+        for (j, x) in row.iter_mut().enumerate() {
+            *x = cofactor(j, i) / determinant;
+        }
+    }
+    inverse
+}
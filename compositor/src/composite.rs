@@ -0,0 +1,133 @@
+use arrayfire::*;
+use std::collections::HashMap;
+use std::rc::Rc;
+use util::MemorySize;
+use crate::encoding::OutputFormat;
+use crate::passes::PassesStruct;
+use crate::spec::{Component, Output};
+
+
+/// Luma or colour components of the passes of one sub-assembly, resident on the device as
+/// (size, size) arrays.
+pub struct LumaStruct {
+    pub channels: HashMap<(String, Component), Array<f32>>,
+}
+
+impl MemorySize for LumaStruct {
+    fn memory_size(&self) -> usize {
+        self.channels.values().map(|x| x.elements()).sum::<usize>() * std::mem::size_of::<f32>()
+    }
+}
+
+
+/// Front, rear and upper of one source, in zmask channel order.
+pub type Assemblies = [Rc<LumaStruct>; 3];
+
+
+/// Uploads `channels` of the sub-assembly, weighting R, G and B by `luma_weights` for luma.
+pub fn upload(exr: &PassesStruct, channels: &[(String, Component)], luma_weights: [f32; 3], size: u64) -> LumaStruct {
+    let dims = dim4!(size, size, 3);
+    let luma = Array::new(&luma_weights, dim4!(1, 1, 3));
+
+    let channels = channels.iter().map(|(pass, component)| {
+        let a_pass = Array::new(&exr.passes[pass], dims);
+        let a_channel = match component {
+            Component::Luma => sum(&mul(&a_pass, &luma, true), 2),
+            Component::R => view!(a_pass[1:1:0, 1:1:0, 0:0:1]),
+            Component::G => view!(a_pass[1:1:0, 1:1:0, 1:1:1]),
+            Component::B => view!(a_pass[1:1:0, 1:1:0, 2:2:1]),
+        };
+        a_channel.eval();
+        ((pass.clone(), *component), a_channel)
+    }).collect();
+
+    LumaStruct { channels }
+}
+
+
+/// Front, rear and upper ownership masks of a zmask, and the coverage of the product.
+pub struct Masks {
+    pub owners: [Array<bool>; 3],
+    /// 0 on the background to 1.
+    pub coverage: Array<f32>,
+}
+
+/// Masks of an RGBA zmask; zmasks without alpha are read as fully covered.
+pub fn masks(zmask: &[u8], size: u64) -> Masks {
+    let mut a_zmask = Array::new(zmask, dim4!(4, size, size));
+    a_zmask = reorder_v2(&a_zmask, 1, 2, Some(vec![0]));
+    let a_owners = a_zmask.cast::<bool>();
+
+    // NOTE: `1:1:0` means all elements along axis
+    Masks {
+        owners: [
+            view!(a_owners[1:1:0, 1:1:0, 0:0:1]),
+            view!(a_owners[1:1:0, 1:1:0, 1:1:1]),
+            view!(a_owners[1:1:0, 1:1:0, 2:2:1]),
+        ],
+        coverage: div(&view!(a_zmask[1:1:0, 1:1:0, 3:3:1]).cast::<f32>(), &255_f32, true),
+    }
+}
+
+
+/// Interleaved RGB, or RGBA for outputs with `alpha`, of a composited map, typed by the output's
+/// format.
+pub enum Pixels {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    F32(Vec<f32>),
+}
+
+
+pub fn composite(
+    output: &Output,
+    sources: &HashMap<String, Assemblies>,
+    masks: &Masks,
+    size: u64,
+) -> Pixels {
+
+    let dims = dim4!(size, size, if output.alpha { 4 } else { 3 });
+    let elements = dims.elements() as usize;
+
+    let channels = output.channels.iter().map(|channel| {
+        let mut a_channel = constant::<f32>(0_f32, dim4!(size, size, 1));
+        if let Some(channel) = channel {
+            for (assembly, mask) in sources[&channel.source].iter().zip(&masks.owners) {
+                a_channel = select(&assembly.channels[&(channel.pass.clone(), channel.component)], mask, &a_channel);
+            }
+        }
+        a_channel
+    }).collect::<Vec<_>>();
+
+    let mut a_out = join_many![2; &channels[0], &channels[1], &channels[2]];
+    if let Some(max_code) = output.format.max_code() {
+        a_out = output.encoding.encode_array(&a_out, max_code);
+    }
+    // Coverage is linear, as codes over the full range in integer formats
+    if output.alpha {
+        let a_alpha = match output.format.max_code() {
+            Some(max_code) => round(&mul(&masks.coverage, &max_code, true)),
+            None => masks.coverage.clone(),
+        };
+        a_out = join(2, &a_out, &a_alpha);
+    }
+    a_out = reorder_v2(&a_out, 2, 0, Some(vec![1]));
+
+    match output.format {
+        OutputFormat::Webp => {
+            let mut pixels = vec!(0; elements);
+            a_out.cast::<u8>().host::<u8>(&mut pixels);
+            Pixels::U8(pixels)
+        }
+        OutputFormat::Png16 | OutputFormat::WebpSplit => {
+            let mut pixels = vec!(0; elements);
+            a_out.cast::<u16>().host::<u16>(&mut pixels);
+            Pixels::U16(pixels)
+        }
+        OutputFormat::ExrHalf => {
+            let mut pixels = vec!(0.0; elements);
+            a_out.host::<f32>(&mut pixels);
+            Pixels::F32(pixels)
+        }
+    }
+}
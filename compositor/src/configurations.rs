@@ -0,0 +1,65 @@
+/// Output configuration name followed by the front, rear and upper sub-assemblies it is made of.
+pub type Configuration = (String, String, String, String);
+
+
+pub struct ConfigOptions<'a> {
+    pub style: Vec<&'a str>,
+    pub guard: Vec<&'a str>,
+    pub caliber: Vec<&'a str>,
+    pub size: Vec<&'a str>,
+    pub ext: Vec<&'a str>,
+    pub rear: Vec<&'a str>,
+    pub rchk: Vec<&'a str>,
+    pub fchk: Vec<&'a str>,
+}
+
+impl Default for ConfigOptions<'static> {
+    fn default() -> Self {
+        ConfigOptions  {
+            style: vec!["Std", "Tac"],
+            guard: vec!["", "Sqr"],
+            caliber: vec![".45", "9mm", "10mm", ".38", ".40"],
+            size: vec!["Com", "Gov"],
+            ext: vec!["", "Ext"],
+            rear: vec!["", "Bob"],
+            rchk: vec!["", "RChk"],
+            fchk: vec!["", "FChk"],
+        }
+    }
+}
+
+
+pub fn get_name(v: Vec<&&str>) -> String {
+    v.into_iter().map(|x| {x.to_owned()}).filter(|x| {!x.is_empty()}).collect::<Vec<&str>>().join(" ").trim_end().to_string()
+}
+
+
+/// Configurations are ordered so that consecutive ones share as many sub-assemblies as possible.
+/// Front and upper both depend on size and ext, so those vary slowest; each caliber then needs one
+/// upper, four fronts and the eight rears, which lets a small cache decode every EXR only once.
+pub fn get_configurations(configs: &mut Vec<Configuration>, options: ConfigOptions) {
+    let trig = "";
+    let sight = "Novak";
+    for size in &options.size {
+        for ext in &options.ext {
+            for caliber in &options.caliber {
+                for style in &options.style {
+                    for guard in &options.guard {
+                        for rear in &options.rear {
+                            for rchk in &options.rchk {
+                                for fchk in &options.fchk {
+                                    configs.push((
+                                        get_name(vec![style, guard, caliber, size, ext, rear, rchk, fchk]),
+                                        get_name(vec![&"Front", style, guard, size, ext]),
+                                        get_name(vec![&"Rear", &"9mm", rear, rchk, fchk, &trig]),
+                                        get_name(vec![&"Upper", caliber, size, ext, &sight]),
+                                    ));
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use arrayfire::*;
+use clap::ArgEnum;
+use exr::prelude::f16;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+
+/// How an output's maps are stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ArgEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    /// 8-bit log codes in a lossless WebP.
+    #[default]
+    Webp,
+    /// 16-bit log codes in a PNG.
+    Png16,
+    /// 16-bit log codes split over two lossless WebPs, the high byte in `{frame}.webp` and the low
+    /// byte in `{frame}.lo.webp`. Readers that only load the first get the 8-bit map.
+    WebpSplit,
+    /// Linear luma as half floats in an EXR, not log encoded.
+    ExrHalf,
+}
+
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 4] = [OutputFormat::Webp, OutputFormat::Png16, OutputFormat::WebpSplit, OutputFormat::ExrHalf];
+
+    /// Largest log code, or `None` for linear formats.
+    pub fn max_code(&self) -> Option<f32> {
+        match self {
+            OutputFormat::Webp => Some(255.0),
+            OutputFormat::Png16 | OutputFormat::WebpSplit => Some(65535.0),
+            OutputFormat::ExrHalf => None,
+        }
+    }
+
+    /// How the codes are laid out in the files, for the manifest.
+    pub fn packing(&self) -> &'static str {
+        match self {
+            OutputFormat::Webp => "8-bit codes in R, G and B",
+            OutputFormat::Png16 => "16-bit codes in R, G and B",
+            OutputFormat::WebpSplit => "high bytes of 16-bit codes in R, G and B, low bytes in the .lo.webp",
+            OutputFormat::ExrHalf => "linear half floats in R, G and B",
+        }
+    }
+
+    /// Extension of the file that tells whether a map has been written.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Webp | OutputFormat::WebpSplit => "webp",
+            OutputFormat::Png16 => "png",
+            OutputFormat::ExrHalf => "exr",
+        }
+    }
+}
+
+
+/// Quantization error of an encoding, in stops.
+#[derive(Debug, Clone, Copy)]
+pub struct Banding {
+    /// Widest step between neighbouring decoded values, i.e. the height of the visible bands.
+    pub band_stops: f32,
+    pub max_error_stops: f32,
+    pub rms_error_stops: f32,
+}
+
+
+/// Maps luminance to 8 or 16-bit codes on a logarithmic scale.
+///
+/// Luminance is measured in stops relative to `middle_grey`, and `min_stops..max_stops` is spread
+/// over the code range after raising it to `1 / gamma`. Values outside the range clamp to the first
+/// or last code. Zero, negative and NaN luminance have no logarithm and are written as code 0,
+/// which therefore decodes to black.
+///
+/// The defaults reproduce the original `(log2(luma) + 12.473931188) * 0.04 * 2 * 255` encoding:
+/// 12.5 stops, 10 below and 2.5 above middle grey.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogEncoding {
+    pub middle_grey: f32,
+    pub min_stops: f32,
+    pub max_stops: f32,
+    pub gamma: f32,
+}
+
+impl Default for LogEncoding {
+    fn default() -> Self {
+        Self {
+            middle_grey: 0.18,
+            min_stops: -10.0,
+            max_stops: 2.5,
+            gamma: 1.0,
+        }
+    }
+}
+
+impl LogEncoding {
+    pub fn from_file(path: &Path) -> Self {
+        let text = fs::read_to_string(path).unwrap_or_else(|e| panic!("Error: cannot read encoding {:?} ({})", path, e));
+        serde_json::from_str(&text).unwrap_or_else(|e| panic!("Error: invalid encoding {:?} ({})", path, e))
+    }
+
+    /// Position of `luma` within the encoded range, 0 to 1.
+    fn normalize(&self, luma: f32) -> f32 {
+        let stops = (luma / self.middle_grey).log2();
+        let t = ((stops - self.min_stops) / (self.max_stops - self.min_stops)).clamp(0.0, 1.0);
+        if self.gamma == 1.0 { t } else { t.powf(1.0 / self.gamma) }
+    }
+
+    fn quantize(&self, luma: f32, max_code: f32) -> f32 {
+        if luma.is_nan() || luma <= 0.0 {
+            return 0.0;
+        }
+        (self.normalize(luma) * max_code).floor()
+    }
+
+    fn dequantize(&self, code: f32, max_code: f32) -> f32 {
+        if code == 0.0 {
+            return 0.0;
+        }
+        let t = ((code + 0.5) / max_code).min(1.0).powf(self.gamma);
+        let stops = self.min_stops + t * (self.max_stops - self.min_stops);
+        self.middle_grey * stops.exp2()
+    }
+
+    pub fn encode(&self, luma: f32) -> u8 {
+        self.quantize(luma, 255.0) as u8
+    }
+
+    /// Luminance at the centre of the range of values written as `code`; code 0 is black.
+    pub fn decode(&self, code: u8) -> f32 {
+        self.dequantize(code as f32, 255.0)
+    }
+
+    pub fn encode16(&self, luma: f32) -> u16 {
+        self.quantize(luma, 65535.0) as u16
+    }
+
+    pub fn decode16(&self, code: u16) -> f32 {
+        self.dequantize(code as f32, 65535.0)
+    }
+
+    /// Measures the banding of storing a log ramp over the encoded range in `format`.
+    pub fn banding(&self, format: OutputFormat) -> Banding {
+        const SAMPLES: usize = 100_000;
+
+        let mut decoded: Vec<(f32, f32)> = Vec::with_capacity(SAMPLES);
+        for i in 0..SAMPLES {
+            let stops = self.min_stops + (self.max_stops - self.min_stops) * (i as f32 + 0.5) / SAMPLES as f32;
+            let luma = self.middle_grey * stops.exp2();
+            let value = match format.max_code() {
+                Some(max_code) => self.dequantize(self.quantize(luma, max_code), max_code),
+                None => f16::from_f32(luma).to_f32(),
+            };
+            // Code 0 is reserved for black, the darkest band has no decoded value
+            if value > 0.0 {
+                decoded.push((stops, (value / self.middle_grey).log2()));
+            }
+        }
+
+        let errors = decoded.iter().map(|(x, y)| (x - y).abs()).collect::<Vec<_>>();
+        let band_stops = decoded.windows(2)
+            .filter(|x| x[0].1 != x[1].1)
+            .map(|x| x[1].1 - x[0].1)
+            .fold(0.0, f32::max);
+
+        Banding {
+            band_stops,
+            max_error_stops: errors.iter().cloned().fold(0.0, f32::max),
+            rms_error_stops: (errors.iter().map(|x| x * x).sum::<f32>() / errors.len() as f32).sqrt(),
+        }
+    }
+
+    /// Device version of `encode`, returning codes as floats ready to cast.
+    pub fn encode_array(&self, a_luma: &Array<f32>, max_code: f32) -> Array<f32> {
+        let valid = gt(a_luma, &0_f32, true);
+
+        let mut a_code = log2(&div(a_luma, &self.middle_grey, true));
+        a_code = sub(&a_code, &self.min_stops, true);
+        a_code = div(&a_code, &(self.max_stops - self.min_stops), true);
+        a_code = clamp(&a_code, &0_f32, &1_f32, true);
+        if self.gamma != 1.0 {
+            a_code = pow(&a_code, &(1.0 / self.gamma), true);
+        }
+        a_code = floor(&mul(&a_code, &max_code, true));
+
+        select(&a_code, &valid, &constant(0_f32, a_code.dims()))
+    }
+}
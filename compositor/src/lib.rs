@@ -0,0 +1,242 @@
+use image::EncodableLayout;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::time::Instant;
+use util::{BuildDb, CommonArgs, FileHashes, ImageWriter, LruCache, Manifest, ManifestEntry, WebpCompressionType, print_summary, run_on_devices, save_exr_half, save_png16, save_webp, thread_pool, use_device};
+
+mod colour;
+mod composite;
+mod configurations;
+mod encoding;
+mod passes;
+mod spec;
+
+pub use colour::{Chromaticities, WorkingSpace};
+pub use composite::{Assemblies, LumaStruct, Masks, Pixels, composite, masks, upload};
+pub use configurations::{ConfigOptions, Configuration, get_configurations, get_name};
+pub use encoding::{Banding, LogEncoding, OutputFormat};
+pub use passes::{PassesStruct, is_channel, pass_path, read_passes_exr};
+pub use spec::{ChannelSpec, Component, Output, OutputMetadata, Spec};
+
+
+/// One configuration of a frame to composite: its zmask, the front, rear and upper EXRs of each
+/// source the spec uses, and where to write each output of the spec.
+pub struct CompositeJob {
+    pub frame: u32,
+    pub config: String,
+    /// Upper sub-assembly; configurations sharing it also share their fronts and rears.
+    pub upper: String,
+    pub zmask: PathBuf,
+    /// Sub-assembly EXRs of each source, in zmask channel order.
+    pub sources: Vec<(String, [PathBuf; 3])>,
+    /// Path of each output of the spec, in the same order.
+    pub outputs: Vec<PathBuf>,
+}
+
+
+/// Path of a frame of sub-assembly, configuration or zmask `name`:
+/// `{dir}/{base_resolution}/{name}/{level}/{frame}.{extension}`, frames numbered from 121.
+pub fn frame_path(dir: &Path, base_resolution: u32, name: &str, level: u32, frame: u32, extension: &str) -> PathBuf {
+    dir.join(format!("{}/{}/{}/{:0>4}", base_resolution, name, level, (121 + frame).to_string())).with_extension(extension)
+}
+
+
+/// Tool version and settings an output's maps depend on, for the build database.
+pub fn output_params(spec: &Spec, output: &Output, resolution: u32) -> String {
+    let metadata = serde_json::to_string(&output.metadata(spec.working_space, spec.luma_weights())).unwrap();
+    format!("compositor {} resolution {} {}", env!("CARGO_PKG_VERSION"), resolution, metadata)
+}
+
+
+/// How to decode an output's maps, as listed in the manifest.
+pub fn manifest_encoding(spec: &Spec, output: &Output) -> serde_json::Value {
+    let mut encoding = serde_json::to_value(output.metadata(spec.working_space, spec.luma_weights())).unwrap();
+    encoding["packing"] = match output.alpha {
+        true => format!("{}, coverage in A", output.format.packing()).into(),
+        false => output.format.packing().into(),
+    };
+    encoding
+}
+
+
+/// Output index, configuration, frame and path of a file written, and whether it holds the low
+/// bytes of a split map.
+type Written = (usize, String, u32, PathBuf, bool);
+
+
+/// Sub-assemblies cached by one device's thread.
+struct DeviceCaches {
+    cache: LruCache<PathBuf, PassesStruct>,
+    gpu_cache: LruCache<PathBuf, LumaStruct>,
+}
+
+
+/// Fetches a sub-assembly from the device cache, falling back to host memory and then to disk.
+fn load(caches: &mut DeviceCaches, path: &Path, spec: &Spec, source: &str, resolution: u32) -> Rc<LumaStruct> {
+    let cache = &mut caches.cache;
+    caches.gpu_cache.get_or_load(path, || {
+        let exr = cache.get_or_load(path, || read_passes_exr(path, &spec.passes(source), spec.per_pass_files.contains(source), resolution, &spec.working_space.chromaticities()));
+        upload(&exr, &spec.channels(source), spec.luma_weights(), resolution as u64)
+    })
+}
+
+
+/// Composites the outputs of `jobs` that are missing or whose inputs or settings changed, spread
+/// across the devices, and records them in `db`. `memory_budget` bounds (MiB) the decoded
+/// sub-assemblies kept in host memory, split between devices, and `gpu_memory_budget` those kept
+/// on each device; either is unbounded if `None`.
+pub fn write_stale_maps(spec: &Spec, jobs: Vec<CompositeJob>, memory_budget: Option<u64>, gpu_memory_budget: Option<u64>, common: &CommonArgs, db: &mut BuildDb) {
+    let devices = &common.devices();
+    let overwrite = common.overwrite;
+
+    let resolution = common.resolution();
+
+    // The host budget is shared by all devices, each device gets the full device budget
+    let budget = memory_budget.map(|mib| mib as usize * 1024 * 1024 / devices.len());
+    let gpu_budget = gpu_memory_budget.map(|mib| mib as usize * 1024 * 1024);
+
+    let sources = spec.used_sources();
+    let passes = sources.iter().map(|x| (x.to_string(), spec.passes(x))).collect::<HashMap<_, _>>();
+    let channels = sources.iter().map(|x| (x.to_string(), spec.channels(x))).collect::<HashMap<_, _>>();
+    let working_space = spec.working_space.chromaticities();
+    // The zmask and sub-assemblies an output of a configuration is made from
+    let inputs = |job: &CompositeJob, output: &Output| {
+        let mut inputs = vec![job.zmask.clone()];
+        for (source, x) in &job.sources {
+            if output.channels.iter().flatten().any(|x| x.source == *source) {
+                inputs.extend(x.iter().flat_map(|x| spec.files(source, x)));
+            }
+        }
+        inputs
+    };
+    let params = spec.outputs.iter().map(|x| output_params(spec, x, resolution)).collect::<Vec<_>>();
+    let db = Mutex::new(db);
+    let hashes = FileHashes::default();
+    // Every file written, for the manifest
+    let written: Mutex<Vec<Written>> = Mutex::new(Vec::new());
+
+    // Indices of the outputs that are missing or whose inputs or settings changed. The inputs are
+    // hashed outside the lock so the devices don't wait on each other's EXRs.
+    let pending_outputs = |job: &CompositeJob| {
+        (0..spec.outputs.len()).filter(|&i| {
+            if overwrite {
+                return true;
+            }
+            let inputs = inputs(job, &spec.outputs[i]);
+            let fingerprint = hashes.fingerprint(&inputs);
+            !db.lock().unwrap().is_current_with_fingerprint(&job.outputs[i], &inputs, fingerprint.as_deref(), &params[i])
+        }).collect::<Vec<_>>()
+    };
+
+    for output in &spec.outputs {
+        output.write_metadata(spec.working_space, spec.luma_weights());
+    }
+
+    let pool = thread_pool(common.jobs);
+    let threads = pool.current_num_threads();
+    let writer = ImageWriter::new(threads);
+
+    // Configurations sharing an upper also share their fronts and rears, so each device takes a
+    // whole group at a time to keep its cache effective
+    let pending = jobs.into_iter().filter(|x| !pending_outputs(x).is_empty()).collect::<Vec<_>>();
+    let groups = pending.chunk_by(|a, b| (a.frame, &a.upper) == (b.frame, &b.upper)).collect::<Vec<_>>();
+
+    let init = |device: i32| {
+        use_device(common.backend(), device);
+        DeviceCaches {
+            cache: LruCache::new(budget),
+            gpu_cache: LruCache::new(gpu_budget),
+        }
+    };
+
+    let work = |caches: &mut DeviceCaches, group: &&[CompositeJob]| {
+        for (i, job) in group.iter().enumerate() {
+            // Decode the sub-assemblies this and the following configurations are missing in parallel
+            if job.sources.iter().flat_map(|(_, x)| x).any(|x| !caches.gpu_cache.contains(x)) {
+                let mut missing: Vec<(String, PathBuf)> = Vec::new();
+                for (source, path) in group[i..].iter().flat_map(|x| x.sources.iter().flat_map(|(s, p)| p.iter().map(|x| (s.clone(), x.clone())))) {
+                    if !caches.gpu_cache.contains(&path) && !missing.iter().any(|(_, x)| *x == path) {
+                        missing.push((source, path));
+                    }
+                    if missing.len() == threads {
+                        break;
+                    }
+                }
+
+                let decoded: Vec<PassesStruct> = pool.install(|| missing.par_iter().map(|(source, path)| read_passes_exr(path, &passes[source], spec.per_pass_files.contains(source), resolution, &working_space)).collect());
+                for ((source, path), exr) in missing.into_iter().zip(decoded) {
+                    caches.gpu_cache.insert(path.clone(), upload(&exr, &channels[&source], spec.luma_weights(), resolution as u64));
+                    caches.cache.insert(path, exr);
+                }
+            }
+
+            let assemblies = job.sources.iter().map(|(source, x)| {
+                let loaded = x.each_ref().map(|path| load(caches, path, spec, source, resolution));
+                (source.clone(), loaded)
+            }).collect::<HashMap<_, _>>();
+
+            let zmask = image::open(&job.zmask).unwrap().to_rgba8().as_bytes().to_vec();
+            let a_masks = masks(&zmask, resolution as u64);
+
+            for index in pending_outputs(job) {
+                let output = &spec.outputs[index];
+                let path_out = job.outputs[index].clone();
+                let fingerprint = hashes.fingerprint(&inputs(job, output));
+                db.lock().unwrap().record_fingerprint(&path_out, fingerprint.as_deref(), &params[index]);
+                written.lock().unwrap().push((index, job.config.clone(), job.frame, path_out.clone(), false));
+                if output.format == OutputFormat::WebpSplit {
+                    written.lock().unwrap().push((index, job.config.clone(), job.frame, path_out.with_extension("lo.webp"), true));
+                }
+
+                let folder = path_out.parent().unwrap();
+                if !folder.exists() {
+                    let _ = fs::create_dir_all(folder);
+                }
+
+                match composite(output, &assemblies, &a_masks, resolution as u64) {
+                    Pixels::U8(pixels) => writer.submit(path_out, resolution, pixels, WebpCompressionType::LOSSLESS),
+                    Pixels::U16(pixels) if output.format == OutputFormat::WebpSplit => {
+                        let path_lo = path_out.with_extension("lo.webp");
+                        writer.submit_with(move || {
+                            let hi = pixels.iter().map(|x| (x >> 8) as u8).collect::<Vec<_>>();
+                            let lo = pixels.iter().map(|x| (x & 0xff) as u8).collect::<Vec<_>>();
+                            save_webp(path_out, resolution, &hi, WebpCompressionType::LOSSLESS);
+                            save_webp(path_lo, resolution, &lo, WebpCompressionType::LOSSLESS);
+                        });
+                    }
+                    Pixels::U16(pixels) => writer.submit_with(move || save_png16(path_out, resolution, &pixels)),
+                    Pixels::F32(pixels) => writer.submit_with(move || save_exr_half(path_out, resolution, &pixels)),
+                }
+            }
+        }
+
+        format!("{} ({} configurations, {} sub-assemblies decoded so far)", group[0].upper, group.len(), caches.cache.loads())
+    };
+
+    let start = Instant::now();
+    let summaries = run_on_devices(devices, &groups, init, work);
+    drop(writer);
+    db.into_inner().unwrap().save();
+    print_summary(&summaries, start.elapsed());
+
+    if let Some(manifest) = &common.manifest {
+        let written = written.into_inner().unwrap();
+        Manifest::update(manifest, |manifest| {
+            for (i, output) in spec.outputs.iter().enumerate() {
+                let lo_name = format!("{}.lo", output.name);
+                let entries = written.iter().filter(|x| x.0 == i).map(|(_, config, frame, path, lo)| ManifestEntry {
+                    product: if *lo { &lo_name } else { &output.name },
+                    config,
+                    resolution,
+                    frame: *frame,
+                    path,
+                }).collect::<Vec<_>>();
+                manifest.add(&manifest_encoding(spec, output), &entries);
+            }
+        });
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use util::{ExrChannels, MemorySize, RGBAChannel, matches_channel};
+use crate::colour::Chromaticities;
+
+
+/// RGB passes of one sub-assembly render, each stored planar as (R, G, B).
+pub struct PassesStruct {
+    pub resolution: usize,
+    pub passes: HashMap<String, Vec<f32>>,
+}
+
+impl PassesStruct {
+    fn new(resolution: usize) -> Self {
+        Self {
+            resolution,
+            passes: HashMap::new(),
+        }
+    }
+
+    fn set_channel(&mut self, channel_data: Vec<f32>, pass: &str, channel: RGBAChannel) {
+
+        let n = self.resolution * self.resolution;
+        if channel_data.len() != n {
+            panic!("Error: channel data has incorrect length ({:?})", channel_data.len());
+        }
+
+        let offset = n * match channel {
+            RGBAChannel::R => 0,
+            RGBAChannel::G => 1,
+            RGBAChannel::B => 2,
+            RGBAChannel::A => 3,
+        };
+
+        let data = self.passes.entry(pass.to_string()).or_insert_with(|| vec![0_f32; n * 3]);
+        data.splice(offset..offset+n, channel_data);
+    }
+
+    /// Applies a 3x3 RGB matrix to every pass.
+    fn transform(&mut self, m: &[[f32; 3]; 3]) {
+        let n = self.resolution * self.resolution;
+        for data in self.passes.values_mut() {
+            for i in 0..n {
+                let rgb = [data[i], data[n + i], data[2 * n + i]];
+                for (c, row) in m.iter().enumerate() {
+                    data[c * n + i] = row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2];
+                }
+            }
+        }
+    }
+}
+
+impl MemorySize for PassesStruct {
+    fn memory_size(&self) -> usize {
+        self.passes.values().map(|x| x.len()).sum::<usize>() * std::mem::size_of::<f32>()
+    }
+}
+
+
+/// Blender prefixes channels with the view layer name (`ViewLayer.Diffuse.R`), so match on the
+/// trailing `{pass}.{channel}` only.
+pub fn is_channel(name: &str, pass: &str, channel: &str) -> bool {
+    matches_channel(name, &format!("{}.{}", pass, channel))
+}
+
+
+/// File holding `pass` alone, for sources rendered to one file per pass: `0121.Diffuse.exr` for
+/// the render `0121.exr`.
+pub fn pass_path(path: &Path, pass: &str) -> PathBuf {
+    path.with_extension(format!("{}.exr", pass))
+}
+
+
+/// Reads the RGB of `passes`, converted from the file's chromaticities to `working_space`. Passes
+/// are looked up in every layer and part, and with `per_pass_files` each in its own file.
+pub fn read_passes_exr(path: &Path, passes: &[String], per_pass_files: bool, resolution: u32, working_space: &Chromaticities) -> PassesStruct {
+    let files = match per_pass_files {
+        true => passes.iter().map(|x| (pass_path(path, x), vec![x])).collect(),
+        false => vec![(path.to_path_buf(), passes.iter().collect::<Vec<_>>())],
+    };
+
+    let mut obj = PassesStruct::new(resolution as usize);
+    for (file, passes) in files {
+        let names = passes.iter().flat_map(|pass| ["R", "G", "B"].map(|x| format!("{}.{}", pass, x))).collect::<Vec<_>>();
+        let exr = ExrChannels::read(&file, resolution, &names);
+
+        // EXR data without the attribute is Rec.709
+        let chromaticities = exr.attributes.chromaticities.map(|x| Chromaticities {
+            red: [x.red.0, x.red.1],
+            green: [x.green.0, x.green.1],
+            blue: [x.blue.0, x.blue.1],
+            white: [x.white.0, x.white.1],
+        }).unwrap_or(Chromaticities::REC709);
+
+        // Outside the rendered region is black
+        let mut part = PassesStruct::new(resolution as usize);
+        for pass in passes {
+            for (name, channel) in [("R", RGBAChannel::R), ("G", RGBAChannel::G), ("B", RGBAChannel::B)] {
+                match exr.find(&format!("{}.{}", pass, name)) {
+                    Some(ch) => part.set_channel(exr.floats(ch, 0.0), pass, channel),
+                    None => panic!("Error: {:?} has no channel {}.{}", file, pass, name),
+                }
+            }
+        }
+
+        if chromaticities != *working_space {
+            part.transform(&chromaticities.conversion(working_space));
+        }
+        obj.passes.extend(part.passes);
+    }
+
+    obj
+}
@@ -0,0 +1,200 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::colour::WorkingSpace;
+use crate::encoding::{LogEncoding, OutputFormat};
+use crate::passes::pass_path;
+
+
+/// What to composite: the renders to read from and the maps to produce from them.
+///
+/// ```json
+/// {
+///   "sources": { "raw": "/renders/raw", "polish": "/renders/polish" },
+///   "working_space": "rec709",
+///   "outputs": [
+///     {
+///       "name": "metal",
+///       "dir": "/maps/metal",
+///       "channels": [
+///         { "source": "raw", "pass": "Glossy" },
+///         { "source": "polish", "pass": "Glossy", "component": "luma" },
+///         null
+///       ],
+///       "encoding": { "middle_grey": 0.18, "min_stops": -10.0, "max_stops": 2.5, "gamma": 1.0 },
+///       "format": "png16",
+///       "alpha": true
+///     }
+///   ]
+/// }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct Spec {
+    /// Directories holding `{base}/{assembly}/{level}/{frame}.exr` renders, by name.
+    pub sources: BTreeMap<String, PathBuf>,
+    /// Colour space inputs are converted to from their `chromaticities`; Rec.709 if omitted.
+    #[serde(default)]
+    pub working_space: WorkingSpace,
+    /// Weights of R, G and B in the luma of a pass; derived from the working space if omitted.
+    #[serde(default)]
+    pub luma_weights: Option<[f32; 3]>,
+    /// Sources rendered to one file per pass, `{frame}.{pass}.exr`, instead of one multi-pass EXR.
+    #[serde(default)]
+    pub per_pass_files: BTreeSet<String>,
+    pub outputs: Vec<Output>,
+}
+
+/// One map written to `{dir}/{base}/{config}/{level}/{frame}.{ext}`, the extension following `format`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Output {
+    pub name: String,
+    pub dir: PathBuf,
+    /// R, G and B of the output. `None` leaves the channel black.
+    pub channels: [Option<ChannelSpec>; 3],
+    #[serde(default)]
+    pub encoding: LogEncoding,
+    #[serde(default)]
+    pub format: OutputFormat,
+    /// LUT the viewer applies to the decoded maps, e.g. `filmic_desat65cube.bin`.
+    #[serde(default)]
+    pub lut: Option<String>,
+    /// Also write the zmask's coverage as alpha, so the viewer can place the product on any background.
+    #[serde(default)]
+    pub alpha: bool,
+}
+
+/// Written as `encoding.json` in an output's directory so readers know how to decode the maps.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct OutputMetadata {
+    pub name: String,
+    pub channels: [Option<ChannelSpec>; 3],
+    pub encoding: LogEncoding,
+    #[serde(default)]
+    pub format: OutputFormat,
+    #[serde(default)]
+    pub working_space: WorkingSpace,
+    pub luma_weights: [f32; 3],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lut: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub alpha: bool,
+}
+
+/// An output channel holding the log encoded luma, or one colour component, of `pass` as rendered
+/// in `source`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChannelSpec {
+    pub source: String,
+    pub pass: String,
+    #[serde(default)]
+    pub component: Component,
+}
+
+/// Which part of a pass's colour a channel holds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Component {
+    #[default]
+    Luma,
+    R,
+    G,
+    B,
+}
+
+
+impl Output {
+    pub fn metadata(&self, working_space: WorkingSpace, luma_weights: [f32; 3]) -> OutputMetadata {
+        OutputMetadata {
+            name: self.name.clone(),
+            channels: self.channels.clone(),
+            encoding: self.encoding,
+            format: self.format,
+            working_space,
+            luma_weights,
+            lut: self.lut.clone(),
+            alpha: self.alpha,
+        }
+    }
+
+    /// Writes `encoding.json` into the output directory, replacing it when the settings changed.
+    /// Maps encoded with the old settings are then stale in the build database, see
+    /// `output_params`, and re-encoded.
+    pub fn write_metadata(&self, working_space: WorkingSpace, luma_weights: [f32; 3]) {
+        let path = self.dir.join("encoding.json");
+        let metadata = self.metadata(working_space, luma_weights);
+
+        let existing: Option<OutputMetadata> = fs::read_to_string(&path).ok().and_then(|x| serde_json::from_str(&x).ok());
+        if existing.as_ref() == Some(&metadata) {
+            return;
+        }
+
+        let _ = fs::create_dir_all(&self.dir);
+        fs::write(&path, serde_json::to_string_pretty(&metadata).unwrap()).unwrap();
+    }
+}
+
+
+impl Spec {
+    pub fn from_file(path: &Path) -> Self {
+        let text = fs::read_to_string(path).unwrap_or_else(|e| panic!("Error: cannot read spec {:?} ({})", path, e));
+        let spec: Spec = serde_json::from_str(&text).unwrap_or_else(|e| panic!("Error: invalid spec {:?} ({})", path, e));
+        spec.validate();
+        spec
+    }
+
+    pub fn validate(&self) {
+        for output in &self.outputs {
+            for channel in output.channels.iter().flatten() {
+                if !self.sources.contains_key(&channel.source) {
+                    panic!("Error: output '{}' reads from unknown source '{}'", output.name, channel.source);
+                }
+            }
+        }
+        for source in &self.per_pass_files {
+            if !self.sources.contains_key(source) {
+                panic!("Error: per_pass_files lists unknown source '{}'", source);
+            }
+        }
+    }
+
+    pub fn luma_weights(&self) -> [f32; 3] {
+        self.luma_weights.unwrap_or_else(|| self.working_space.chromaticities().luma_weights())
+    }
+
+    /// Sources that at least one output reads from.
+    pub fn used_sources(&self) -> Vec<&str> {
+        self.sources.keys().map(|x| x.as_str()).filter(|x| !self.passes(x).is_empty()).collect()
+    }
+
+    /// Passes and components the outputs need from `source`.
+    pub fn channels(&self, source: &str) -> Vec<(String, Component)> {
+        let mut channels: Vec<(String, Component)> = Vec::new();
+        for channel in self.outputs.iter().flat_map(|x| x.channels.iter().flatten()) {
+            let key = (channel.pass.clone(), channel.component);
+            if channel.source == source && !channels.contains(&key) {
+                channels.push(key);
+            }
+        }
+        channels
+    }
+
+    /// Files read for the render of `source` at `path`: the render itself, or a file per pass.
+    pub fn files(&self, source: &str, path: &Path) -> Vec<PathBuf> {
+        match self.per_pass_files.contains(source) {
+            true => self.passes(source).iter().map(|x| pass_path(path, x)).collect(),
+            false => vec![path.to_path_buf()],
+        }
+    }
+
+    /// Passes the outputs need from `source`.
+    pub fn passes(&self, source: &str) -> Vec<String> {
+        let mut passes: Vec<String> = Vec::new();
+        for channel in self.outputs.iter().flat_map(|x| x.channels.iter().flatten()) {
+            if channel.source == source && !passes.contains(&channel.pass) {
+                passes.push(channel.pass.clone());
+            }
+        }
+        passes
+    }
+}
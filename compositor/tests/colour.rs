@@ -0,0 +1,41 @@
+use compositor::Chromaticities;
+
+type Matrix = [[f32; 3]; 3];
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    [0, 1, 2].map(|i| [0, 1, 2].map(|j| (0..3).map(|k| a[i][k] * b[k][j]).sum()))
+}
+
+fn assert_near(m: &Matrix, expected: &Matrix, tolerance: f32) {
+    for (row, expected) in m.iter().zip(expected) {
+        for (x, y) in row.iter().zip(expected) {
+            assert!((x - y).abs() <= tolerance, "{:?} differs from {:?}", m, expected);
+        }
+    }
+}
+
+const IDENTITY: Matrix = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+
+#[test]
+fn conversion_to_the_same_space_is_identity() {
+    for space in [Chromaticities::REC709, Chromaticities::REC2020, Chromaticities::ACESCG] {
+        assert_near(&space.conversion(&space), &IDENTITY, 1e-6);
+    }
+}
+
+
+#[test]
+fn rec709_to_acescg_round_trips() {
+    let to_acescg = Chromaticities::REC709.conversion(&Chromaticities::ACESCG);
+    let to_rec709 = Chromaticities::ACESCG.conversion(&Chromaticities::REC709);
+    assert_near(&multiply(&to_rec709, &to_acescg), &IDENTITY, 1e-5);
+
+    // The Bradford adapted matrix published for ACEScg, which keeps white white
+    let expected = [
+        [0.6131, 0.3395, 0.0474],
+        [0.0702, 0.9164, 0.0134],
+        [0.0206, 0.1096, 0.8698],
+    ];
+    assert_near(&to_acescg, &expected, 1e-3);
+}
@@ -0,0 +1,160 @@
+use arrayfire::*;
+use compositor::*;
+use golden::{assert_golden, pattern, rgb_pass, scratch_dir, write_exr};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+use std::rc::Rc;
+
+const SIZE: u32 = 32;
+
+
+/// Front, rear and upper renders of one source. Each assembly has its own tint and brightness so
+/// the output shows which one a pixel came from.
+fn assemblies(dir: &Path, spec: &Spec, source: &str, glossy: f32) -> Assemblies {
+    let tints = [[1.0, 1.0, 1.0], [1.0, 0.5, 0.25], [0.25, 0.5, 1.0]];
+
+    [0, 1, 2].map(|i| {
+        let scale = [1.0, 0.5, 2.0][i];
+        let tint = tints[i];
+        let channel = |f: &dyn Fn(f32, f32) -> f32| tint.map(|t| pattern(SIZE, |x, y| t * f(x, y)));
+
+        let diffuse = channel(&|_, _| 0.18 * scale);
+        let glossy = channel(&|x, y| glossy * scale * (0.1 + x * y));
+        let ao = channel(&|x, _| 0.25 + 0.75 * x);
+
+        let mut channels = Vec::new();
+        channels.extend(rgb_pass("Diffuse", [&diffuse[0], &diffuse[1], &diffuse[2]]));
+        channels.extend(rgb_pass("Glossy", [&glossy[0], &glossy[1], &glossy[2]]));
+        channels.extend(rgb_pass("AO", [&ao[0], &ao[0], &ao[0]]));
+
+        let path = dir.join(source).join(format!("{}.exr", i));
+        write_exr(&path, SIZE, channels);
+
+        let exr = read_passes_exr(&path, &spec.passes(source), false, SIZE, &spec.working_space.chromaticities());
+        Rc::new(upload(&exr, &spec.channels(source), spec.luma_weights(), SIZE as u64))
+    })
+}
+
+
+/// Front on the left, rear in the middle and upper on the right, over a transparent bottom half.
+fn zmask() -> Vec<u8> {
+    let third = |x: f32| (x * 3.0) as usize;
+    pattern(SIZE, |x, _| x).into_iter().zip(pattern(SIZE, |_, y| y)).flat_map(|(x, y)| {
+        [(third(x) == 0) as u8, (third(x) == 1) as u8, (third(x) == 2) as u8, if y < 0.5 { 255 } else { 0 }]
+    }).collect()
+}
+
+
+fn output(name: &str, channels: [Option<ChannelSpec>; 3]) -> Output {
+    Output {
+        name: name.to_string(),
+        dir: Default::default(),
+        channels,
+        encoding: LogEncoding::default(),
+        format: OutputFormat::Webp,
+        lut: None,
+        alpha: false,
+    }
+}
+
+fn channel(source: &str, pass: &str, component: Component) -> Option<ChannelSpec> {
+    Some(ChannelSpec { source: source.to_string(), pass: pass.to_string(), component })
+}
+
+fn composite_u8(output: &Output, sources: &HashMap<String, Assemblies>) -> Vec<u8> {
+    match composite(output, sources, &masks(&zmask(), SIZE as u64), SIZE as u64) {
+        Pixels::U8(pixels) => pixels,
+        _ => panic!("Expected 8-bit output"),
+    }
+}
+
+
+#[test]
+fn foreground_matches_golden() {
+    set_backend(Backend::CPU);
+    let dir = scratch_dir("foreground");
+
+    let luma = |pass: &str| channel("foreground", pass, Component::Luma);
+    let rgb = |pass: &str| [Component::R, Component::G, Component::B].map(|c| channel("foreground", pass, c));
+    let spec = Spec {
+        sources: BTreeMap::from([("foreground".to_string(), dir.clone())]),
+        working_space: WorkingSpace::Rec709,
+        luma_weights: None,
+        per_pass_files: BTreeSet::new(),
+        outputs: vec![
+            output("light", [luma("Diffuse"), luma("Glossy"), luma("AO")]),
+            output("glossy", rgb("Glossy")),
+        ],
+    };
+
+    let sources = HashMap::from([("foreground".to_string(), assemblies(&dir, &spec, "foreground", 1.0))]);
+
+    let light = composite_u8(&spec.outputs[0], &sources);
+    assert_golden("foreground_light_0121", SIZE, &light, 1);
+    assert_golden("foreground_glossy_0121", SIZE, &composite_u8(&spec.outputs[1], &sources), 1);
+
+    // The front's diffuse is middle grey
+    let encoding = LogEncoding::default();
+    assert!(light[3 * (16 * SIZE + 2) as usize].abs_diff(encoding.encode(0.18)) <= 1);
+}
+
+
+#[test]
+fn metal_matches_golden() {
+    set_backend(Backend::CPU);
+    let dir = scratch_dir("metal");
+
+    let glossy = |source: &str| channel(source, "Glossy", Component::Luma);
+    let spec = Spec {
+        sources: BTreeMap::from([("raw".to_string(), dir.join("raw")), ("polish".to_string(), dir.join("polish"))]),
+        working_space: WorkingSpace::Rec709,
+        luma_weights: None,
+        per_pass_files: BTreeSet::new(),
+        outputs: vec![output("metal", [glossy("raw"), glossy("polish"), None])],
+    };
+
+    let sources = HashMap::from([
+        ("raw".to_string(), assemblies(&dir, &spec, "raw", 0.5)),
+        ("polish".to_string(), assemblies(&dir, &spec, "polish", 4.0)),
+    ]);
+
+    let metal = composite_u8(&spec.outputs[0], &sources);
+    assert_golden("metal_0121", SIZE, &metal, 1);
+
+    // Unused channels stay black
+    assert!(metal.chunks(3).all(|x| x[2] == 0));
+}
+
+
+#[test]
+fn alpha_is_the_zmask_coverage() {
+    set_backend(Backend::CPU);
+    let dir = scratch_dir("alpha");
+
+    let luma = |pass: &str| channel("foreground", pass, Component::Luma);
+    let mut light = output("light", [luma("Diffuse"), luma("Glossy"), luma("AO")]);
+    let spec = Spec {
+        sources: BTreeMap::from([("foreground".to_string(), dir.clone())]),
+        working_space: WorkingSpace::Rec709,
+        luma_weights: None,
+        per_pass_files: BTreeSet::new(),
+        outputs: vec![light.clone()],
+    };
+    let sources = HashMap::from([("foreground".to_string(), assemblies(&dir, &spec, "foreground", 1.0))]);
+    let rgb = composite_u8(&light, &sources);
+
+    light.alpha = true;
+    let rgba = composite_u8(&light, &sources);
+    assert_eq!(rgba.len(), 4 * (SIZE * SIZE) as usize);
+
+    // Colour is unchanged, alpha is opaque over the top half only
+    assert!(rgba.chunks(4).zip(rgb.chunks(3)).all(|(a, b)| a[..3] == *b));
+    let alpha = |x: u32, y: u32| rgba[4 * (y * SIZE + x) as usize + 3];
+    assert_eq!((alpha(2, 2), alpha(2, SIZE - 2)), (255, 0));
+
+    light.format = OutputFormat::ExrHalf;
+    match composite(&light, &sources, &masks(&zmask(), SIZE as u64), SIZE as u64) {
+        Pixels::F32(pixels) => assert_eq!((pixels[3], pixels[pixels.len() - 1]), (1.0, 0.0)),
+        _ => panic!("Expected float output"),
+    }
+}
@@ -0,0 +1,65 @@
+use compositor::*;
+use golden::{pattern, scratch_dir, write_exr, write_exr_parts};
+
+const SIZE: u32 = 8;
+
+
+fn rgb(pass: &str, value: f32) -> Vec<(String, Vec<f32>)> {
+    ["R", "G", "B"].map(|c| (format!("{}.{}", pass, c), pattern(SIZE, |_, _| value))).to_vec()
+}
+
+fn read(path: &std::path::Path, passes: &[&str], per_pass_files: bool) -> PassesStruct {
+    let passes = passes.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+    read_passes_exr(path, &passes, per_pass_files, SIZE, &Chromaticities::REC709)
+}
+
+
+#[test]
+fn passes_are_found_in_every_part() {
+    let path = scratch_dir("passes_parts").join("0121.exr");
+    write_exr_parts(&path, SIZE, vec![
+        ("ViewLayer".to_string(), rgb("Diffuse", 0.25)),
+        ("Reflections".to_string(), rgb("Glossy", 0.5)),
+    ]);
+
+    let exr = read(&path, &["Diffuse", "Glossy"], false);
+    assert!(exr.passes["Diffuse"].iter().all(|x| *x == 0.25));
+    assert!(exr.passes["Glossy"].iter().all(|x| *x == 0.5));
+}
+
+#[test]
+fn layer_names_select_the_view_layer() {
+    let path = scratch_dir("passes_layers").join("0121.exr");
+    write_exr_parts(&path, SIZE, vec![
+        ("Background".to_string(), rgb("Diffuse", 0.25)),
+        ("Foreground".to_string(), rgb("Diffuse", 0.75)),
+    ]);
+
+    let exr = read(&path, &["Foreground.Diffuse"], false);
+    assert!(exr.passes["Foreground.Diffuse"].iter().all(|x| *x == 0.75));
+}
+
+#[test]
+fn per_pass_files_are_combined() {
+    let path = scratch_dir("passes_files").join("0121.exr");
+    write_exr(&pass_path(&path, "Diffuse"), SIZE, rgb("ViewLayer.Diffuse", 0.25));
+    write_exr(&pass_path(&path, "Glossy"), SIZE, rgb("ViewLayer.Glossy", 0.5));
+
+    let exr = read(&path, &["Diffuse", "Glossy"], true);
+    assert!(exr.passes["Diffuse"].iter().all(|x| *x == 0.25));
+    assert!(exr.passes["Glossy"].iter().all(|x| *x == 0.5));
+}
+
+#[test]
+fn only_requested_channels_are_read() {
+    let path = scratch_dir("passes_wanted").join("0121.exr");
+    write_exr_parts(&path, SIZE, vec![
+        ("ViewLayer".to_string(), [rgb("Diffuse", 0.25), rgb("Glossy", 0.5)].concat()),
+        ("CryptoMaterial".to_string(), rgb("CryptoMaterial00", 1.0)),
+    ]);
+
+    let wanted = ["Diffuse.R".to_string(), "Diffuse.G".to_string()];
+    let exr = util::ExrChannels::read(&path, SIZE, &wanted);
+    let names = exr.channels.iter().map(|x| x.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, ["ViewLayer.Diffuse.G", "ViewLayer.Diffuse.R"]);
+}
@@ -0,0 +1,389 @@
+use arrayfire::*;
+use exr::meta::MetaData;
+use rayon::prelude::*;
+use std::path::{Path, PathBuf};
+use std::ops::{Not};
+use std::time::Instant;
+mod temporal;
+pub use temporal::{FrameFlicker, TURN, flicker, flips, neighbours, print_flicker, stabilize, stabilize_zmasks};
+
+use util::{BuildDb, CommonArgs, ExrChannels, ImageWriter, Manifest, ManifestEntry, WebpCompressionType, channel_list, matches_channel, print_summary, run_on_devices, thread_pool, use_device};
+
+
+/// Depth channels in order of preference: Blender's multi-layer `ViewLayer.Depth.Z`, the
+/// `Depth.V` of other renderers, then a bare `Z`, which must not be prefixed so that e.g.
+/// `Normal.Z` isn't taken for depth.
+pub const DEPTH_CHANNELS: [&str; 3] = ["Depth.Z", "Depth.V", "Z"];
+
+/// Blender writes this depth where no surface was hit.
+pub const BACKGROUND_DEPTH: f32 = 1e10;
+
+
+/// Name of the depth channel among the channel `names`, see `DEPTH_CHANNELS`.
+pub fn depth_channel<'a>(names: impl Iterator<Item = &'a str> + Clone) -> Option<String> {
+    DEPTH_CHANNELS.iter().find_map(|channel| match *channel {
+        "Z" => names.clone().find(|x| *x == "Z"),
+        _ => names.clone().find(|x| matches_channel(x, channel)),
+    }).map(|x| x.to_string())
+}
+
+
+/// Coverage rendered alongside the depth, read to give the zmask an anti-aliased alpha.
+pub const ALPHA_CHANNEL: &str = "Combined.A";
+
+
+/// Depth of a `resolution` square EXR, and its coverage if rendered, `resolution²` values each.
+pub struct DepthExr {
+    pub z: Vec<f32>,
+    /// `ALPHA_CHANNEL`, 0 outside the rendered region.
+    pub alpha: Option<Vec<f32>>,
+}
+
+/// Reads the depth of a `resolution` square EXR. Background, whether written as
+/// `BACKGROUND_DEPTH`, infinity or NaN, and pixels outside the rendered region are all infinitely
+/// far, so they are behind every assembly and the clipping plane.
+pub fn read_depth_exr(path: &Path, resolution: u32) -> DepthExr {
+    let meta = MetaData::read_from_file(path, false).unwrap_or_else(|e| panic!("Error: cannot read {:?} ({})", path, e));
+    let list = channel_list(&meta);
+    let name = depth_channel(list.iter().map(|x| x.1.as_str()))
+        .unwrap_or_else(|| panic!("Error: {:?} has no depth channel, expected one of {}", path, DEPTH_CHANNELS.join(", ")));
+    let alpha = list.iter().map(|x| &x.1).find(|x| matches_channel(x, ALPHA_CHANNEL)).cloned();
+
+    let exr = ExrChannels::read(path, resolution, &[Some(name.clone()), alpha.clone()].into_iter().flatten().collect::<Vec<_>>());
+    let channel = |name: &str| exr.channels.iter().find(|x| x.name == name).unwrap();
+
+    let mut z = exr.floats(channel(&name), f32::INFINITY);
+    for x in &mut z {
+        if x.is_nan() || *x >= BACKGROUND_DEPTH {
+            *x = f32::INFINITY;
+        }
+    }
+    DepthExr {
+        z,
+        alpha: alpha.map(|x| exr.floats(channel(&x), 0.0)),
+    }
+}
+
+
+/// Coverage of any of the front, rear and upper sub-assemblies, if all of them have an alpha.
+pub fn coverage(assemblies: [&DepthExr; 3]) -> Option<Vec<f32>> {
+    let [front, rear, upper] = assemblies.map(|x| x.alpha.as_ref());
+    Some(front?.iter().zip(rear?).zip(upper?).map(|((a, b), c)| a.max(*b).max(*c)).collect())
+}
+
+/// Front, rear and upper ownership of each pixel as 0/1 RGB, from the depth of each assembly and of
+/// the clipping plane, and the coverage of the product as alpha, 0 to 255. The alpha is `coverage`
+/// if given, keeping anti-aliased edges, and else 255 wherever an assembly was rendered or a pixel
+/// is owned.
+pub fn depth_mask(frame: usize, z_front: &Vec<f32>, z_rear: &Vec<f32>, z_upper: &Vec<f32>, z_plane: &Vec<f32>, coverage: Option<&[f32]>, size: u64) -> Vec<u8> {
+    let dims = dim4!(size, size);
+    let batch = false;
+    let mask = constant::<bool>(true, dim4!(3, 3));
+    
+    for z in [z_front, z_rear, z_upper, z_plane] {
+        assert_eq!(z.len() as u64, size * size, "depth of {} values for a {}x{} zmask", z.len(), size, size);
+    }
+
+    let a_front = Array::new(z_front, dims);
+    let a_rear = Array::new(z_rear, dims);
+    let a_upper = Array::new(z_upper, dims);
+    let a_plane = Array::new(z_plane, dims);
+
+    let f_r = lt(&a_front, &a_rear, batch);
+    let f_u = lt(&a_front, &a_upper, batch);
+    let r_u = lt(&a_rear, &a_upper, batch);
+    let r_f = lt(&a_rear, &a_front, batch);
+    let u_f = lt(&a_upper, &a_front, batch);
+    let u_r = lt(&a_upper, &a_rear, batch);
+
+    let mut m_front = f_r & f_u;
+    let mut m_rear = r_f & r_u;
+    let m_upper = u_f & u_r;
+
+    let r_p = match frame % 24 {
+        // Right side view
+        0 => {
+            let n = dims[0] as i32;
+            let p = gt(&range::<i32>(dim4!(n as u64), 0), &((n * 49/50)>>1), true);
+            and(&m_rear, &p, true)
+        },
+
+        // Facing away
+        1..=11 => {
+            let p = ge(&a_rear, &a_plane, true);
+            and(&m_rear, &p, batch)
+        },
+
+        // Left side view
+        12 => {
+            let n = dims[0] as i32;
+            let p = le(&range::<i32>(dim4!(n as u64), 0), &((n * 51/50)>>1), true);
+            and(&m_rear, &p, true)
+        },
+
+        // Facing toward
+        13..=23 => {
+            let p = lt(&a_rear, &a_plane, true);
+            and(&m_rear, &p, batch)
+        },
+        _ => panic!("This should never happen")
+    };
+
+    m_front = or(&m_front, &r_p, batch);
+    m_rear = and(&m_rear, &r_p.not(), batch);
+
+    let d_front = and(&dilate(&m_front, &mask), &lt(&or(&m_rear, &m_upper, batch), &1, true), batch);
+    let mut d_rear = and(&dilate(&m_rear, &mask), &lt(&or(&m_front, &m_upper, batch), &1, true), batch);
+    let mut d_upper = and(&dilate(&m_upper, &mask), &lt(&or(&m_front, &m_rear, batch), &1, true), batch);
+
+    d_upper = and(&d_upper, &d_rear.not(), batch);
+    d_upper = and(&d_upper, &d_front.not(), batch);
+    d_rear = and(&d_rear, &d_front.not(), batch);
+
+    // Background has infinite depth in all three assemblies
+    let a_coverage = match coverage {
+        Some(x) => clamp(&Array::new(x, dims), &0_f32, &1_f32, true),
+        None => {
+            let rendered = or(&or(&isinf(&a_front).not(), &isinf(&a_rear).not(), batch), &isinf(&a_upper).not(), batch);
+            let owned = or(&or(&d_front, &d_rear, batch), &d_upper, batch);
+            or(&rendered, &owned, batch).cast::<f32>()
+        }
+    };
+    let a_alpha = round(&mul(&a_coverage, &255_f32, true));
+
+    let mut buffer = vec!(0; 4 * dims.elements() as usize);
+    let owners = join_many![2; &d_front, &d_rear, &d_upper].cast::<u8>();
+    let mut ar = join(2, &owners, &a_alpha.cast::<u8>());
+    ar = reorder_v2(&ar, 2, 0, Some(vec![1]));
+    ar.host::<u8>(&mut buffer);
+
+    return buffer;
+}
+
+/// One zmask to compute: the front, rear, upper and plane depth EXRs of a frame, and where to
+/// write the mask.
+pub struct ZmaskJob {
+    /// Frame index from 0, which decides the view.
+    pub frame: usize,
+    /// Configuration the mask is listed under in the manifest, empty if unknown.
+    pub config: String,
+    pub inputs: [PathBuf; 4],
+    pub output: PathBuf,
+}
+
+
+/// Tool version and settings a zmask depends on, for the build database.
+pub fn zmask_params(frame: usize, size: u32, temporal_margin: Option<f32>) -> String {
+    let params = format!("depth {} rgba resolution {} view {}", env!("CARGO_PKG_VERSION"), size, frame % 24);
+    match temporal_margin {
+        Some(margin) => format!("{} temporal {}", params, margin),
+        None => params,
+    }
+}
+
+
+/// Computes and writes `jobs`, spread across the devices, and records them in `db`.
+pub fn write_zmasks(jobs: &[ZmaskJob], size: u32, common: &CommonArgs, db: &mut BuildDb) {
+    let devices = &common.devices();
+
+    let pool = thread_pool(common.jobs);
+    let threads = pool.current_num_threads();
+    let writer = ImageWriter::new(threads);
+
+    // Each frame reads four EXRs, so every device decodes enough frames at once to keep the threads busy
+    let chunks = jobs.chunks(threads.div_ceil(4 * devices.len())).collect::<Vec<_>>();
+
+    let init = |device: i32| use_device(common.backend(), device);
+
+    let work = |_: &mut (), chunk: &&[ZmaskJob]| {
+        let inputs: Vec<Vec<DepthExr>> = pool.install(|| chunk.par_iter().map(|job| {
+            job.inputs.par_iter().map(|path| read_depth_exr(path, size)).collect()
+        }).collect());
+
+        for (job, z) in chunk.iter().zip(inputs) {
+            let (z_front, z_rear, z_upper, z_plane) = (&z[0].z, &z[1].z, &z[2].z, &z[3].z);
+            let coverage = coverage([&z[0], &z[1], &z[2]]);
+
+            let zmask = depth_mask(job.frame, z_front, z_rear, z_upper, z_plane, coverage.as_deref(), size as u64);
+
+            writer.submit(job.output.clone(), size, zmask, WebpCompressionType::LOSSLESS);
+        }
+
+        format!("{:?} to {:?}", chunk[0].output, chunk[chunk.len() - 1].output)
+    };
+
+    let start = Instant::now();
+    let summaries = run_on_devices(devices, &chunks, init, work);
+    drop(writer);
+    print_summary(&summaries, start.elapsed());
+
+    for job in jobs {
+        db.record(&job.output, &job.inputs, &zmask_params(job.frame, size, None));
+    }
+    db.save();
+
+    if let Some(manifest) = &common.manifest {
+        let entries = jobs.iter().map(|job| ManifestEntry {
+            product: "zmask",
+            config: &job.config,
+            resolution: size,
+            frame: job.frame as u32,
+            path: &job.output,
+        }).collect::<Vec<_>>();
+        Manifest::update(manifest, |manifest| manifest.add(&zmask_encoding(), &entries));
+    }
+}
+
+
+/// How to decode the zmasks, as listed in the manifest.
+pub fn zmask_encoding() -> serde_json::Value {
+    serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "packing": "R, G and B are 1 where the front, rear or upper sub-assembly is shown, else 0; A is the coverage of the product, 0 on the background to 255",
+    })
+}
+
+
+/// Writes the zmasks of `jobs` that are missing or whose depth renders changed. With a
+/// `temporal_margin` a changed frame redoes its whole turn, since its neighbours decide its
+/// temporal pass, and the flicker of each frame is printed.
+pub fn write_stale_zmasks(jobs: Vec<ZmaskJob>, size: u32, temporal_margin: Option<f32>, common: &CommonArgs, db: &mut BuildDb) {
+    let stale = jobs.iter().map(|job| common.overwrite || !db.is_current(&job.output, &job.inputs, &zmask_params(job.frame, size, temporal_margin))).collect::<Vec<_>>();
+
+    let turns = jobs.iter().zip(&stale).filter(|x| *x.1).map(|x| x.0.frame / TURN).collect::<Vec<_>>();
+    let pending = jobs.into_iter().zip(stale).filter(|(job, stale)| match temporal_margin {
+        Some(_) => turns.contains(&(job.frame / TURN)),
+        None => *stale,
+    }).map(|x| x.0).collect::<Vec<_>>();
+    write_zmasks(&pending, size, common, db);
+
+    if let Some(margin) = temporal_margin {
+        print_flicker(&stabilize_zmasks(&pending, size, margin, common, db));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exr::prelude::{FlatSamples, f16};
+    use golden::{assert_golden, pattern, scratch_dir, write_exr, write_exr_samples};
+
+    const SIZE: u32 = 32;
+
+    fn rgb(rgba: &[u8]) -> Vec<u8> {
+        rgba.chunks(4).flat_map(|x| &x[..3]).copied().collect()
+    }
+
+    /// In the top half front is nearest on the left, rear on the right and upper along the top;
+    /// the bottom half is all rear, nearer than the plane above three quarters down. Rear then
+    /// owns pixels on both sides of the plane and of the side view column cutoffs, and there is
+    /// a background hole in the corner where nothing was rendered.
+    fn fixtures(dir: &Path) -> Vec<Vec<f32>> {
+        let background = 1e10_f32;
+        let hole = |x: f32, y: f32, z: f32| if x > 0.85 && y > 0.85 { background } else { z };
+        let depths = [
+            ("front", pattern(SIZE, |x, y| hole(x, y, if y > 0.5 { 3.0 } else { 1.0 + 2.0 * x }))),
+            ("rear", pattern(SIZE, |x, y| hole(x, y, if y > 0.5 { 1.0 + y } else { 3.0 - 2.0 * x }))),
+            ("upper", pattern(SIZE, |x, y| hole(x, y, 1.2 + 2.0 * y))),
+            ("plane", pattern(SIZE, |_, _| 1.75)),
+        ];
+
+        depths.into_iter().map(|(name, z)| {
+            let path = dir.join(name).with_extension("exr");
+            write_exr(&path, SIZE, vec![("ViewLayer.Depth.Z".to_string(), z)]);
+            read_depth_exr(&path, SIZE).z
+        }).collect()
+    }
+
+    #[test]
+    fn depth_mask_matches_golden() {
+        set_backend(Backend::CPU);
+        let z = fixtures(&scratch_dir("depth"));
+
+        // Right side, facing away, left side and facing toward each hand a different part of rear to front
+        let zmasks = [0, 6, 12, 18].map(|frame| {
+            let zmask = depth_mask(frame, &z[0], &z[1], &z[2], &z[3], None, SIZE as u64);
+            assert_golden(&format!("depth_{:0>4}", 121 + frame), SIZE, &rgb(&zmask), 0);
+            zmask
+        });
+        for (i, a) in zmasks.iter().enumerate() {
+            for b in &zmasks[i + 1..] {
+                assert_ne!(a, b, "two views gave the same zmask");
+            }
+        }
+    }
+
+    #[test]
+    fn depth_mask_assigns_nearest_assembly() {
+        set_backend(Backend::CPU);
+        let z = fixtures(&scratch_dir("depth_nearest"));
+
+        let zmask = depth_mask(6, &z[0], &z[1], &z[2], &z[3], None, SIZE as u64);
+        let pixel = |x: u32, y: u32| &zmask[4 * (y * SIZE + x) as usize..][..4];
+
+        assert_eq!(pixel(2, 16), [1, 0, 0, 255]);
+        assert_eq!(pixel(16, 2), [0, 0, 1, 255]);
+    }
+
+    #[test]
+    fn depth_mask_background_is_transparent() {
+        set_backend(Backend::CPU);
+        let z = fixtures(&scratch_dir("depth_alpha"));
+
+        let zmask = depth_mask(6, &z[0], &z[1], &z[2], &z[3], None, SIZE as u64);
+        let corner = &zmask[4 * (SIZE * SIZE - 1) as usize..];
+        assert_eq!(corner, [0, 0, 0, 0]);
+
+        // Combined.A gives the right edge partial coverage, which owned pixels keep
+        let coverage = pattern(SIZE, |x, y| if x > 0.85 && y > 0.85 { 0.0 } else if x > 0.85 { 0.5 } else { 1.0 });
+        let zmask = depth_mask(6, &z[0], &z[1], &z[2], &z[3], Some(&coverage), SIZE as u64);
+        let pixel = |x: u32, y: u32| &zmask[4 * (y * SIZE + x) as usize..][..4];
+        assert_eq!(pixel(SIZE - 1, SIZE - 1), [0, 0, 0, 0]);
+        assert_eq!(pixel(SIZE - 1, 16), [0, 1, 0, 128]);
+        assert_eq!(pixel(2, 16), [1, 0, 0, 255]);
+    }
+
+    #[test]
+    #[should_panic(expected = "is 32x32 but 64x64 was expected")]
+    fn read_depth_exr_rejects_other_resolutions() {
+        let path = scratch_dir("depth_resolution").join("front.exr");
+        write_exr(&path, SIZE, vec![("ViewLayer.Depth.Z".to_string(), pattern(SIZE, |_, _| 1.0))]);
+
+        read_depth_exr(&path, 64);
+    }
+
+    #[test]
+    fn read_depth_exr_widens_half_floats() {
+        let path = scratch_dir("depth_half").join("front.exr");
+        let z = pattern(SIZE, |x, y| 1.0 + x + 2.0 * y);
+        write_exr_samples(&path, SIZE, vec![("ViewLayer.Depth.Z".to_string(), FlatSamples::F16(z.iter().map(|x| f16::from_f32(*x)).collect()))]);
+
+        let v = read_depth_exr(&path, SIZE).z;
+        assert_eq!(v, z.iter().map(|x| f16::from_f32(*x).to_f32()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn depth_channel_is_picked_by_name() {
+        let names = |x: &[&'static str]| depth_channel(x.iter().copied());
+        assert_eq!(names(&["ViewLayer.Depth.Z", "ViewLayer.Normal.X", "ViewLayer.Normal.Z"]), Some("ViewLayer.Depth.Z".to_string()));
+        assert_eq!(names(&["Depth.V", "Normal.Z"]), Some("Depth.V".to_string()));
+        assert_eq!(names(&["B", "G", "R", "Z"]), Some("Z".to_string()));
+        assert_eq!(names(&["ViewLayer.Normal.Z"]), None);
+    }
+
+    #[test]
+    fn read_depth_exr_pushes_background_to_infinity() {
+        let path = scratch_dir("depth_background").join("front.exr");
+        let z = pattern(SIZE, |x, _| if x < 0.25 { BACKGROUND_DEPTH } else if x < 0.5 { f32::NAN } else { 2.0 });
+        write_exr(&path, SIZE, vec![
+            ("ViewLayer.Depth.Z".to_string(), z),
+            ("ViewLayer.Normal.Z".to_string(), pattern(SIZE, |_, _| 0.5)),
+        ]);
+
+        let v = read_depth_exr(&path, SIZE).z;
+        assert_eq!(v.len(), (SIZE * SIZE) as usize);
+        assert!(v.iter().all(|x| *x == 2.0 || *x == f32::INFINITY));
+        assert_eq!(v[0], f32::INFINITY);
+        assert_eq!(v[(SIZE - 1) as usize], 2.0);
+    }
+}
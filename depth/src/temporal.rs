@@ -0,0 +1,177 @@
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use util::{BuildDb, CommonArgs, WebpCompressionType, save_webp, thread_pool};
+use crate::{ZmaskJob, read_depth_exr, zmask_params};
+
+
+/// Frames in one turn of the turntable; frame `f` shows view `f % 24`.
+pub const TURN: usize = 24;
+
+/// Owner of a pixel no sub-assembly claims.
+const NONE: usize = 3;
+
+
+/// Frames before and after `frame` in its turn, wrapping around since the turntable is closed.
+pub fn neighbours(frame: usize) -> (usize, usize) {
+    let turn = frame / TURN * TURN;
+    (turn + (frame + TURN - 1) % TURN, turn + (frame + 1) % TURN)
+}
+
+
+/// Front, rear or upper (0 to 2) owning a pixel of an RGBA zmask, else `NONE`.
+fn owner(rgba: &[u8]) -> usize {
+    rgba[..3].iter().position(|x| *x > 0).unwrap_or(NONE)
+}
+
+/// Pixels of `zmask` owned by another sub-assembly, or by none, than in both the `previous` and
+/// `next` frames, which agree on a sub-assembly. These are the ownership flips that show as
+/// crawling seams; silhouettes moving over the background aren't counted.
+pub fn flips(previous: &[u8], zmask: &[u8], next: &[u8]) -> Vec<usize> {
+    (0..zmask.len() / 4).filter(|&i| {
+        let [a, b, c] = [previous, zmask, next].map(|x| owner(&x[4 * i..]));
+        a == c && a != NONE && a != b
+    }).collect()
+}
+
+/// Share of the covered pixels of `zmask` that flip for this frame only, see `flips`.
+pub fn flicker(previous: &[u8], zmask: &[u8], next: &[u8]) -> f32 {
+    let covered = zmask.chunks(4).filter(|x| x[3] > 0).count();
+    flips(previous, zmask, next).len() as f32 / covered.max(1) as f32
+}
+
+
+/// Gives the pixels of `zmask` that flip the owner of the neighbouring frames where depth barely
+/// decided: that sub-assembly is rendered there and at most `margin` (relative) farther than the
+/// one shown. `depths` are the front, rear and upper depths of the frame. Returns the number of
+/// pixels changed.
+pub fn stabilize(previous: &[u8], zmask: &mut [u8], next: &[u8], depths: [&[f32]; 3], margin: f32) -> usize {
+    let mut changed = 0;
+    for i in flips(previous, zmask, next) {
+        let (from, to) = (owner(&zmask[4 * i..]), owner(&previous[4 * i..]));
+        let z = depths[to][i];
+        if z.is_finite() && (from == NONE || z <= depths[from][i] * (1.0 + margin)) {
+            zmask[4 * i..4 * i + 3].fill(0);
+            zmask[4 * i + to] = 1;
+            // Anti-aliased coverage is kept, only pixels no assembly owned become opaque
+            if zmask[4 * i + 3] == 0 {
+                zmask[4 * i + 3] = 255;
+            }
+            changed += 1;
+        }
+    }
+    changed
+}
+
+
+/// Flicker of one frame before and after the temporal pass.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameFlicker {
+    pub frame: usize,
+    pub before: f32,
+    pub after: f32,
+    pub changed: usize,
+}
+
+/// Resolves single-frame ownership flips in the written zmasks of `jobs`, see `stabilize`, and
+/// records them in `db`. Each turn of each configuration must be complete since every frame is
+/// compared with its neighbours, and the zmasks of a whole turn are held in memory at once.
+pub fn stabilize_zmasks(jobs: &[ZmaskJob], size: u32, margin: f32, common: &CommonArgs, db: &mut BuildDb) -> Vec<FrameFlicker> {
+    let mut turns: BTreeMap<(&str, usize), Vec<&ZmaskJob>> = BTreeMap::new();
+    for job in jobs {
+        turns.entry((&job.config, job.frame / TURN)).or_default().push(job);
+    }
+
+    let pool = thread_pool(common.jobs);
+    let mut stats = Vec::new();
+    for ((config, turn), mut jobs) in turns {
+        if jobs.len() != TURN {
+            panic!("Error: the temporal pass needs all {} frames of turn {} of '{}', got {}", TURN, turn, config, jobs.len());
+        }
+        jobs.sort_by_key(|x| x.frame);
+
+        // The flips of every frame are found against the zmasks as computed, not as stabilized
+        let zmasks: Vec<Vec<u8>> = pool.install(|| jobs.par_iter().map(|job| {
+            image::open(&job.output).unwrap_or_else(|e| panic!("Error: cannot read {:?} ({})", job.output, e)).to_rgba8().into_raw()
+        }).collect());
+
+        let turn_stats: Vec<FrameFlicker> = pool.install(|| jobs.par_iter().zip(&zmasks).map(|(job, zmask)| {
+            let (previous, next) = neighbours(job.frame);
+            let (previous, next) = (&zmasks[previous % TURN], &zmasks[next % TURN]);
+            let before = flicker(previous, zmask, next);
+
+            let mut stable = zmask.clone();
+            let mut changed = 0;
+            if before > 0.0 {
+                let depths = job.inputs[..3].iter().map(|x| read_depth_exr(x, size).z).collect::<Vec<_>>();
+                changed = stabilize(previous, &mut stable, next, [&depths[0], &depths[1], &depths[2]], margin);
+            }
+            if changed > 0 {
+                save_webp(job.output.clone(), size, &stable, WebpCompressionType::LOSSLESS);
+            }
+
+            FrameFlicker { frame: job.frame, before, after: flicker(previous, &stable, next), changed }
+        }).collect());
+        stats.extend(turn_stats);
+    }
+
+    for job in jobs {
+        db.record(&job.output, &job.inputs, &zmask_params(job.frame, size, Some(margin)));
+    }
+    db.save();
+    stats
+}
+
+
+/// Prints the flicker of the frames that have any, as a percentage of their covered pixels.
+pub fn print_flicker(stats: &[FrameFlicker]) {
+    println!("{:>5}  {:>8}  {:>8}  {:>8}", "frame", "before", "after", "changed");
+    for x in stats.iter().filter(|x| x.before > 0.0) {
+        println!("{:>5}  {:>7.3}%  {:>7.3}%  {:>8}", x.frame, x.before * 100.0, x.after * 100.0, x.changed);
+    }
+    let mean = |f: fn(&FrameFlicker) -> f32| stats.iter().map(f).sum::<f32>() / stats.len().max(1) as f32;
+    println!("{:>5}  {:>7.3}%  {:>7.3}%  {:>8}", "mean", mean(|x| x.before) * 100.0, mean(|x| x.after) * 100.0, stats.iter().map(|x| x.changed).sum::<usize>());
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RGBA zmask of one row, from the owner of each pixel.
+    fn zmask(owners: &[usize]) -> Vec<u8> {
+        owners.iter().flat_map(|&x| [0, 1, 2].map(|i| (i == x) as u8).into_iter().chain([(x != NONE) as u8 * 255])).collect()
+    }
+
+    #[test]
+    fn neighbours_wrap_within_the_turn() {
+        assert_eq!(neighbours(0), (23, 1));
+        assert_eq!(neighbours(12), (11, 13));
+        assert_eq!(neighbours(47), (46, 24));
+    }
+
+    #[test]
+    fn flips_ignore_the_background() {
+        let previous = zmask(&[0, 1, NONE, 2]);
+        let current = zmask(&[0, 2, 0, NONE]);
+        let next = zmask(&[0, 1, NONE, 2]);
+
+        assert_eq!(flips(&previous, &current, &next), [1, 3]);
+        assert_eq!(flicker(&previous, &current, &next), 2.0 / 3.0);
+    }
+
+    #[test]
+    fn stabilize_only_resolves_ties_in_depth() {
+        let previous = zmask(&[1, 1, 1]);
+        let next = previous.clone();
+        let mut current = zmask(&[0, 0, NONE]);
+
+        // The rear is barely behind the front at the first pixel, far behind at the second
+        let front = [10.0, 10.0, f32::INFINITY];
+        let rear = [10.05, 12.0, 11.0];
+        let upper = [f32::INFINITY; 3];
+        let changed = stabilize(&previous, &mut current, &next, [&front, &rear, &upper], 0.01);
+
+        assert_eq!(changed, 2);
+        assert_eq!(current, zmask(&[1, 0, 1]));
+    }
+}
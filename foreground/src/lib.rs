@@ -0,0 +1,24 @@
+use compositor::{ChannelSpec, Component};
+use std::path::{Path, PathBuf};
+
+
+/// Name of the source the foreground maps are composited from.
+pub const SOURCE: &str = "foreground";
+
+
+/// Outputs of the foreground compositor as `(name, dir, channels)`: the diffuse, glossy and AO
+/// light map in `light` and, with `colour`, the diffuse and glossy passes in colour in
+/// `{colour}/diffuse` and `{colour}/glossy`.
+pub fn outputs(light: &Path, colour: Option<&Path>) -> Vec<(&'static str, PathBuf, [Option<ChannelSpec>; 3])> {
+    let channel = |pass: &str, component: Component| Some(ChannelSpec { source: SOURCE.to_string(), pass: pass.to_string(), component });
+    let pass = |pass: &str| channel(pass, Component::Luma);
+    let rgb = |pass: &str| [channel(pass, Component::R), channel(pass, Component::G), channel(pass, Component::B)];
+
+    let mut outputs = vec![("light", light.to_path_buf(), [pass("Diffuse"), pass("Glossy"), pass("AO")])];
+    if let Some(colour) = colour {
+        for (name, pass) in [("diffuse", "Diffuse"), ("glossy", "Glossy")] {
+            outputs.push((name, colour.join(name), rgb(pass)));
+        }
+    }
+    outputs
+}
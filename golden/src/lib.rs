@@ -0,0 +1,109 @@
+//! Synthetic renders and golden image comparison for the regression tests.
+//!
+//! Expected outputs live in `golden/expected/{name}.png`. Run the tests with `BLESS=1` to
+//! (re)write them from the current output after checking the change is intended.
+
+use exr::prelude::*;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+
+/// Fresh scratch directory for one test.
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("golden-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+
+/// Evaluates `f(x, y)` over a `size` x `size` image in row major order, with `x` and `y` in 0..1.
+pub fn pattern<F: Fn(f32, f32) -> f32>(size: u32, f: F) -> Vec<f32> {
+    let n = size as usize;
+    (0..n * n).map(|i| f((i % n) as f32 / n as f32, (i / n) as f32 / n as f32)).collect()
+}
+
+
+/// Writes a single layer EXR with F32 channels named e.g. `ViewLayer.Diffuse.R`, like Blender.
+pub fn write_exr(path: &PathBuf, size: u32, channels: Vec<(String, Vec<f32>)>) {
+    write_exr_samples(path, size, channels.into_iter().map(|(name, data)| (name, FlatSamples::F32(data))).collect());
+}
+
+
+/// Like `write_exr`, with each channel's sample type given by its samples.
+pub fn write_exr_samples(path: &PathBuf, size: u32, channels: Vec<(String, FlatSamples)>) {
+    let channels = channels.into_iter().map(|(name, data)| AnyChannel::new(name.as_str(), data)).collect();
+
+    let layer = Layer::new(
+        (size as usize, size as usize),
+        LayerAttributes::named(""),
+        Encoding::FAST_LOSSLESS,
+        AnyChannels::sort(SmallVec::from_vec(channels)),
+    );
+
+    let _ = fs::create_dir_all(path.parent().unwrap());
+    Image::from_layer(layer).write().to_file(path).unwrap();
+}
+
+
+/// Layer name and the `(channel name, samples)` of one part.
+pub type Part = (String, Vec<(String, Vec<f32>)>);
+
+/// Writes a multi-part EXR with one F32 part per `(layer name, channels)`, channels named e.g.
+/// `Diffuse.R`, like Blender does for view layers.
+pub fn write_exr_parts(path: &PathBuf, size: u32, parts: Vec<Part>) {
+    let layers = parts.into_iter().map(|(layer, channels)| {
+        let channels = channels.into_iter().map(|(name, data)| AnyChannel::new(name.as_str(), FlatSamples::F32(data))).collect();
+        Layer::new(
+            (size as usize, size as usize),
+            LayerAttributes::named(layer.as_str()),
+            Encoding::FAST_LOSSLESS,
+            AnyChannels::sort(SmallVec::from_vec(channels)),
+        )
+    }).collect::<Vec<_>>();
+
+    let attributes = ImageAttributes::new(IntegerBounds::from_dimensions((size as usize, size as usize)));
+    let _ = fs::create_dir_all(path.parent().unwrap());
+    Image::from_layers(attributes, layers).write().to_file(path).unwrap();
+}
+
+
+/// RGB channels of a pass with the same value in each.
+pub fn rgb_pass(pass: &str, rgb: [&[f32]; 3]) -> Vec<(String, Vec<f32>)> {
+    ["R", "G", "B"].iter().zip(rgb).map(|(c, x)| (format!("ViewLayer.{}.{}", pass, c), x.to_vec())).collect()
+}
+
+
+/// Compares interleaved RGB `pixels` with `expected/{name}.png`, allowing each channel to differ by
+/// `tolerance` codes to absorb floating point differences between backends.
+pub fn assert_golden(name: &str, size: u32, pixels: &[u8], tolerance: u8) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("expected").join(name).with_extension("png");
+
+    if env::var_os("BLESS").is_some() {
+        let _ = fs::create_dir_all(path.parent().unwrap());
+        image::save_buffer(&path, pixels, size, size, image::ColorType::Rgb8).unwrap();
+        return;
+    }
+
+    let expected = image::open(&path)
+        .unwrap_or_else(|e| panic!("Error: cannot read {:?} ({}), run the tests with BLESS=1 to create it", path, e))
+        .to_rgb8();
+    assert_eq!(expected.dimensions(), (size, size), "{} has a different resolution", name);
+
+    let mut mismatches = 0;
+    let mut first = None;
+    for (i, (a, b)) in pixels.iter().zip(expected.as_raw()).enumerate() {
+        if a.abs_diff(*b) > tolerance {
+            mismatches += 1;
+            first.get_or_insert((i / 3, *a, *b));
+        }
+    }
+
+    if let Some((pixel, actual, expected)) = first {
+        panic!(
+            "{}: {} channels differ by more than {}, first at ({}, {}): {} != {}",
+            name, mismatches, tolerance, pixel % size as usize, pixel / size as usize, actual, expected,
+        );
+    }
+}
@@ -0,0 +1,414 @@
+use arrayfire::*;
+use exr::meta::MetaData;
+use rayon::prelude::*;
+use std::mem::{transmute};
+use std::ops::{Not, Shl, Shr};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use util::{BuildDb, CommonArgs, ExrChannels, RGBAChannel, ImageWriter, Manifest, ManifestEntry, WebpCompressionType, channel_list, print_summary, run_on_devices, thread_pool, use_device};
+
+
+/// Cryptomatte ranks of one frame, stored planar as (R, G, B, A) = ranks 0 to 3.
+pub struct MatteStruct {
+    pub resolution: usize,
+    pub index: Vec<u32>,
+    pub matte: Vec<f32>,
+}
+
+/// One channel of a cryptomatte rank: the ID hash bits or the coverage.
+enum MatteData {
+    Index(Vec<u32>),
+    Matte(Vec<f32>),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MattePass {
+    Index,
+    Matte,
+}
+
+/// Channels of a Cryptomatte layer after its name, and the rank they hold. Each of `00` and `01`
+/// holds two ranks as (ID, coverage) pairs in R, G and B, A.
+pub const CRYPTO_CHANNELS: [(&str, MattePass, RGBAChannel); 8] = [
+    ("00.R", MattePass::Index, RGBAChannel::R),
+    ("00.G", MattePass::Matte, RGBAChannel::R),
+    ("00.B", MattePass::Index, RGBAChannel::G),
+    ("00.A", MattePass::Matte, RGBAChannel::G),
+    ("01.R", MattePass::Index, RGBAChannel::B),
+    ("01.G", MattePass::Matte, RGBAChannel::B),
+    ("01.B", MattePass::Index, RGBAChannel::A),
+    ("01.A", MattePass::Matte, RGBAChannel::A),
+];
+
+
+/// Name of the first Cryptomatte layer among the channel `names`, e.g. `ViewLayer.CryptoObject`
+/// for `ViewLayer.CryptoObject00.R`.
+pub fn crypto_layer<'a>(names: impl Iterator<Item = &'a str>) -> Option<String> {
+    names.filter_map(|x| x.strip_suffix("00.R")).find(|x| x.rsplit('.').next().unwrap().starts_with("Crypto")).map(|x| x.to_string())
+}
+
+impl MatteStruct {
+    fn new (resolution: usize) -> Self {
+        let n = resolution * resolution;
+        Self {
+            resolution,
+            index: vec![0_u32; n * 4],
+            matte: vec![0_f32; n * 4],
+        }
+    }
+  
+    fn set_channel(&mut self, channel_data: MatteData, channel: RGBAChannel) {
+      
+        let n = self.resolution * self.resolution;
+        let len = match &channel_data {
+            MatteData::Index(x) => x.len(),
+            MatteData::Matte(x) => x.len(),
+        };
+        if len != n {
+            panic!("Error: channel data has incorrect length ({:?})", len);
+        }
+
+        let offset = n * match channel {
+            RGBAChannel::R => 0,
+            RGBAChannel::G => 1,
+            RGBAChannel::B => 2,
+            RGBAChannel::A => 3,
+        };
+
+        match channel_data {
+            MatteData::Index(x) => { self.index.splice(offset..offset+n, x); },
+            MatteData::Matte(x) => { self.matte.splice(offset..offset+n, x); },
+        };
+    }
+}
+
+
+/// Object index of each cryptomatte ID, with the ID stored as a float.
+pub fn get_index_map() -> [(u32, f32); 32] {
+    [
+        (0, 46.93645477294922), // VOID
+        (1, -0.03498752787709236),
+        (2, -7.442164937651292e-35),
+        (3, -6.816108887753408e+29),
+        (4, 0.00035458870115689933),
+        (5, -2.1174496448267268e-37),
+        (6, 1.4020313126302311e+32),
+        (7, -1.0356748461253123e-29),
+        (8, -2.9085143335341026e+36),
+        (9, 1.3880169547064725e-07),
+        (10, -1.259480075076364e+31),
+        (11, 9.950111644430328e-20),
+        (12, 7.755555963998422e+23),
+        (13, 7.694573644696632e-19),
+        (14, -5.1650727722774545e-23),
+        (15, 9.80960464477539),
+        (16, -2.863075394543557e-07),
+        (17, -1.1106028290273499e+26),
+        (18, 5.081761389253177e+22),
+        (19, -6.4202393950590105e+25),
+        (20, -4.099688753251169e+19),
+        (21, -4.738090716008833e+34),
+        (22, 1.3174184410047474e-08),
+        (23, -0.014175964519381523),
+        (24, 2.4984514311654493e-05),
+        (25, -8.232201253122184e-06),
+        (26, 1.2103820479584479e-20),
+        (27, -2.508242528606597e-12),
+        (28, 1.5731503249895985e+26),
+        (29, 1.4262572893553038e-11),
+        (30, -84473296.0),
+        (31, 0.0), // NONE
+    ]
+}
+
+
+/// Reads the ranks of the first Cryptomatte layer, looked up by name in every layer and part.
+pub fn read_matte_exr(path: &Path, resolution: u32) -> MatteStruct {
+    let meta = MetaData::read_from_file(path, false).unwrap_or_else(|e| panic!("Error: cannot read {:?} ({})", path, e));
+    let layer = crypto_layer(channel_list(&meta).iter().map(|x| x.1.as_str()))
+        .unwrap_or_else(|| panic!("Error: {:?} has no Cryptomatte channels", path));
+
+    let names = CRYPTO_CHANNELS.map(|(suffix, _, _)| format!("{}{}", layer, suffix));
+    let exr = ExrChannels::read(path, resolution, &names);
+
+    let mut obj = MatteStruct::new(resolution as usize);
+
+    // Outside the rendered region there is no object (ID 0.0) and no coverage
+    for ((_, pass, channel), name) in CRYPTO_CHANNELS.into_iter().zip(&names) {
+        let ch = exr.find(name).unwrap_or_else(|| panic!("Error: {:?} has no channel {}", path, name));
+        match pass {
+            MattePass::Index => obj.set_channel(MatteData::Index(exr.ids(ch)), channel),
+            MattePass::Matte => obj.set_channel(MatteData::Matte(exr.floats(ch, 0.0)), channel),
+        }
+    }
+
+    return obj;
+}
+
+
+/// Bit-packed object indices and coverage of ranks 1 to 3, both as interleaved RGB.
+pub fn composite(
+    arr: &[(u32, f32); 32],
+    exr: MatteStruct,
+    size: u64,
+) -> (Vec<u8>, Vec<u8>) {
+    
+    let dim3 = dim4!(size, size, 3);
+    let dim4 = dim4!(size, size, 4);
+
+    let mut index = vec!(0; dim3.elements() as usize);
+    let mut matte = vec!(0; dim3.elements() as usize);
+
+    // Map index values
+    let a_index_copy = Array::new(&exr.index, dim4);
+    let mut a_index = constant(0_u32, dim4);
+    for (k, v) in arr {
+        let cond = eq(&a_index_copy, &constant(unsafe { transmute::<f32, u32>(*v) }, dim4), false);
+        replace(&mut a_index, &cond.not(), &constant(*k, dim4));
+    }
+    
+    // Bit-pack
+    let r_1 = view!(a_index[1:1:0, 1:1:0, 0:0:1]).cast::<u8>();
+    let r_2 = view!(a_index[1:1:0, 1:1:0, 1:1:1]).cast::<u8>();
+    let r_3 = view!(a_index[1:1:0, 1:1:0, 2:2:1]).cast::<u8>();
+    let r_4 = view!(a_index[1:1:0, 1:1:0, 3:3:1]).cast::<u8>();
+
+    let d = dim4!(size, size);
+    let a_r = bitor(&r_1, &bitand(&r_2, &constant(0b11u8, d), false).shl(6_u8), false);
+    let a_g = bitor(&bitand(&r_2, &constant(0b111100u8, d), false).shr(2_u8), &bitand(&r_3, &constant(0b1111u8, d), false).shl(4_u8), false);
+    let a_b = bitor(&bitand(&r_3, &constant(0b110000u8, d), false).shr(4_u8), &r_4.shl(2_u8), false);
+
+    let mut a_index = join_many![2; &a_r, &a_g, &a_b];
+    a_index = reorder_v2(&a_index, 2, 0, Some(vec![1]));
+    a_index.cast::<u8>().host::<u8>(&mut index);
+
+
+    // Matte
+    let mut a_matte = Array::new(&exr.matte, dim4);
+    let mut r_1 = view!(a_matte[1:1:0, 1:1:0, 1:1:1]);
+    let mut r_2 = view!(a_matte[1:1:0, 1:1:0, 2:2:1]);
+    let mut r_3 = view!(a_matte[1:1:0, 1:1:0, 3:3:1]);
+
+    r_1 = clamp(&mul(&r_1, &(2.0_f32 * 255_f32), true), &(0_f32), &(255_f32), true);
+    r_2 = clamp(&mul(&r_2, &(2.0_f32 * 255_f32), true), &(0_f32), &(255_f32), true);
+    r_3 = clamp(&mul(&r_3, &(2.0_f32 * 255_f32), true), &(0_f32), &(255_f32), true);
+
+    a_matte = join_many![2; &r_1, &r_2, &r_3];
+    a_matte = reorder_v2(&a_matte, 2, 0, Some(vec![1]));
+    a_matte.cast::<u8>().host::<u8>(&mut matte);
+
+    return (index, matte);
+}
+
+/// Object indices of ranks 0 to 3 unpacked from one pixel of an index map.
+pub fn unpack_index([r, g, b]: [u8; 3]) -> [u8; 4] {
+    [r & 0x3f, r >> 6 | (g & 0xf) << 2, g >> 4 | (b & 0x3) << 4, b >> 2]
+}
+
+
+/// Number of pixels with a cryptomatte ID at any rank that isn't in `arr`, which `composite`
+/// writes as VOID.
+pub fn unknown_ids(arr: &[(u32, f32); 32], exr: &MatteStruct) -> usize {
+    let known = arr.iter().map(|(_, v)| v.to_bits()).collect::<Vec<_>>();
+    let n = exr.resolution * exr.resolution;
+    (0..n).filter(|i| (0..4).any(|rank| !known.contains(&exr.index[rank * n + i]))).count()
+}
+
+/// One cryptomatte EXR to split into index and matte maps.
+pub struct MatteJob {
+    /// Frame index from 0.
+    pub frame: usize,
+    pub input: PathBuf,
+    pub index: PathBuf,
+    pub matte: PathBuf,
+}
+
+
+/// Tool version and settings the index and matte maps depend on, for the build database.
+pub fn matte_params(size: u32) -> String {
+    format!("matte {} resolution {}", env!("CARGO_PKG_VERSION"), size)
+}
+
+
+/// Computes and writes `jobs`, spread across the devices, and records them in `db`.
+pub fn write_mattes(jobs: &[MatteJob], size: u32, common: &CommonArgs, db: &mut BuildDb) {
+    let devices = &common.devices();
+    let arr = get_index_map();
+
+    let pool = thread_pool(common.jobs);
+    let threads = pool.current_num_threads();
+    let writer = ImageWriter::new(threads);
+
+    // Every device decodes enough frames at once to keep the threads busy
+    let chunks = jobs.chunks(threads.div_ceil(devices.len())).collect::<Vec<_>>();
+
+    let init = |device: i32| use_device(common.backend(), device);
+
+    let work = |_: &mut (), chunk: &&[MatteJob]| {
+        let exrs: Vec<MatteStruct> = pool.install(|| chunk.par_iter().map(|job| read_matte_exr(&job.input, size)).collect());
+
+        for (job, exr) in chunk.iter().zip(exrs) {
+            let (index, matte) = composite(&arr, exr, size as u64);
+
+            writer.submit(job.index.clone(), size, index, WebpCompressionType::LOSSLESS);
+            writer.submit(job.matte.clone(), size, matte, WebpCompressionType::LOSSLESS);
+        }
+
+        format!("{:?} to {:?}", chunk[0].input, chunk[chunk.len() - 1].input)
+    };
+
+    let start = Instant::now();
+    let summaries = run_on_devices(devices, &chunks, init, work);
+    drop(writer);
+    print_summary(&summaries, start.elapsed());
+
+    for job in jobs {
+        let inputs = std::slice::from_ref(&job.input);
+        db.record(&job.index, inputs, &matte_params(size));
+        db.record(&job.matte, inputs, &matte_params(size));
+    }
+    db.save();
+
+    if let Some(manifest) = &common.manifest {
+        let entries = |product: &'static str, path: fn(&MatteJob) -> &PathBuf| jobs.iter().map(|job| ManifestEntry {
+            product,
+            config: "",
+            resolution: size,
+            frame: job.frame as u32,
+            path: path(job),
+        }).collect::<Vec<_>>();
+        Manifest::update(manifest, |manifest| {
+            manifest.add(&index_encoding(), &entries("index", |x| &x.index));
+            manifest.add(&matte_encoding(), &entries("matte", |x| &x.matte));
+        });
+    }
+}
+
+
+/// How to decode the index maps, as listed in the manifest.
+pub fn index_encoding() -> serde_json::Value {
+    serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "packing": "6-bit object indices of ranks 0 to 3 packed little end first over R, G and B",
+    })
+}
+
+
+/// How to decode the matte maps, as listed in the manifest.
+pub fn matte_encoding() -> serde_json::Value {
+    serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "packing": "coverage of ranks 1 to 3 in R, G and B, scaled by 510 and clamped to 255",
+    })
+}
+
+
+/// Writes the index and matte maps of `jobs` that are missing or whose cryptomatte changed.
+pub fn write_stale_mattes(jobs: Vec<MatteJob>, size: u32, common: &CommonArgs, db: &mut BuildDb) {
+    let mut is_current = |output: &Path, input: &PathBuf| db.is_current(output, std::slice::from_ref(input), &matte_params(size));
+    let pending = jobs.into_iter().filter(|job| common.overwrite || !is_current(&job.index, &job.input) || !is_current(&job.matte, &job.input)).collect::<Vec<_>>();
+    write_mattes(&pending, size, common, db);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exr::prelude::{FlatSamples, f16};
+    use golden::{assert_golden, pattern, scratch_dir, write_exr, write_exr_samples};
+
+    const SIZE: u32 = 32;
+
+    /// Two objects meeting at x = 0.5 with anti-aliased coverage across the whole frame, the
+    /// nearer one as rank 0 and the other as rank 1. Ranks 2 and 3 are empty.
+    fn fixture(dir: &Path) -> MatteStruct {
+        let arr = get_index_map();
+        let (left, right) = (arr[1].1, arr[15].1);
+
+        let coverage = |x: f32| 0.5 + (x - 0.5).abs();
+        let channels = [
+            ("Combined.R", pattern(SIZE, |x, y| x * y)),
+            ("Combined.G", pattern(SIZE, |x, y| x * y)),
+            ("Combined.B", pattern(SIZE, |x, y| x * y)),
+            ("Combined.A", pattern(SIZE, |_, _| 1.0)),
+            ("Crypto00.R", pattern(SIZE, |x, _| if x < 0.5 { left } else { right })),
+            ("Crypto00.G", pattern(SIZE, |x, _| coverage(x))),
+            ("Crypto00.B", pattern(SIZE, |x, _| if x < 0.5 { right } else { left })),
+            ("Crypto00.A", pattern(SIZE, |x, _| 1.0 - coverage(x))),
+            ("Crypto01.R", pattern(SIZE, |_, _| 0.0)),
+            ("Crypto01.G", pattern(SIZE, |_, _| 0.0)),
+            ("Crypto01.B", pattern(SIZE, |_, _| 0.0)),
+            ("Crypto01.A", pattern(SIZE, |_, _| 0.0)),
+        ];
+
+        let path = dir.join("0121.exr");
+        write_exr(&path, SIZE, channels.into_iter().map(|(name, x)| (format!("ViewLayer.{}", name), x)).collect());
+        read_matte_exr(&path, SIZE)
+    }
+
+    #[test]
+    fn composite_matches_golden() {
+        set_backend(Backend::CPU);
+        let exr = fixture(&scratch_dir("matte"));
+
+        let (index, matte) = composite(&get_index_map(), exr, SIZE as u64);
+        assert_golden("matte_index_0121", SIZE, &index, 0);
+        assert_golden("matte_matte_0121", SIZE, &matte, 1);
+    }
+
+    #[test]
+    fn composite_packs_object_ids() {
+        set_backend(Backend::CPU);
+        let exr = fixture(&scratch_dir("matte_ids"));
+
+        let (index, matte) = composite(&get_index_map(), exr, SIZE as u64);
+        let pixel = |x: &Vec<u8>, i: u32| x[3 * i as usize..][..3].to_vec();
+
+        // Ranks 1, 15, NONE and NONE packed six bits each
+        let i = 16 * SIZE + 2;
+        assert_eq!(pixel(&index, i), [1 | (15 & 0b11) << 6, 15 >> 2 | (31 & 0b1111) << 4, 31 >> 4 | 31 << 2]);
+        assert_eq!(pixel(&matte, i), [(0.0625_f32 * 510.0) as u8, 0, 0]);
+        assert_eq!(unpack_index(pixel(&index, i).try_into().unwrap()), [1, 15, 31, 31]);
+    }
+
+    #[test]
+    fn unknown_ids_counts_unmapped_pixels() {
+        let mut exr = fixture(&scratch_dir("matte_unknown"));
+        assert_eq!(unknown_ids(&get_index_map(), &exr), 0);
+
+        exr.index[5] = 1.5_f32.to_bits();
+        assert_eq!(unknown_ids(&get_index_map(), &exr), 1);
+    }
+
+    /// Object 1 fully covering the frame as rank 0, with the ID channels stored by `ids`.
+    fn write_single_object(path: &PathBuf, ids: fn(Vec<f32>) -> FlatSamples) {
+        let id = get_index_map()[1].1;
+        let mut channels = ["R", "G", "B", "A"].map(|c| (format!("ViewLayer.Combined.{}", c), FlatSamples::F32(pattern(SIZE, |_, _| 1.0)))).to_vec();
+        for (layer, rank0) in [("Crypto00", true), ("Crypto01", false)] {
+            let (id, coverage) = if rank0 { (id, 1.0) } else { (0.0, 0.0) };
+            channels.push((format!("ViewLayer.{}.R", layer), ids(pattern(SIZE, |_, _| id))));
+            channels.push((format!("ViewLayer.{}.G", layer), FlatSamples::F32(pattern(SIZE, |_, _| coverage))));
+            channels.push((format!("ViewLayer.{}.B", layer), ids(pattern(SIZE, |_, _| 0.0))));
+            channels.push((format!("ViewLayer.{}.A", layer), FlatSamples::F32(pattern(SIZE, |_, _| 0.0))));
+        }
+        write_exr_samples(path, SIZE, channels);
+    }
+
+    #[test]
+    fn read_matte_exr_accepts_integer_ids() {
+        let path = scratch_dir("matte_u32").join("0121.exr");
+        write_single_object(&path, |x| FlatSamples::U32(x.iter().map(|x| x.to_bits()).collect()));
+
+        let exr = read_matte_exr(&path, SIZE);
+        assert!(exr.index[..(SIZE * SIZE) as usize].iter().all(|x| *x == get_index_map()[1].1.to_bits()));
+        assert_eq!(unknown_ids(&get_index_map(), &exr), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "is half float")]
+    fn read_matte_exr_rejects_half_float_ids() {
+        let path = scratch_dir("matte_f16").join("0121.exr");
+        write_single_object(&path, |x| FlatSamples::F16(x.iter().map(|x| f16::from_f32(*x)).collect()));
+        read_matte_exr(&path, SIZE);
+    }
+}
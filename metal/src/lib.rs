@@ -0,0 +1,15 @@
+use compositor::{ChannelSpec, Component};
+use std::path::{Path, PathBuf};
+
+
+/// Names of the sources the metal maps are composited from, the raw and the polished renders.
+pub const SOURCES: [&str; 2] = ["raw", "polish"];
+
+
+/// Outputs of the metal compositor as `(name, dir, channels)`: the glossy luma of the raw and
+/// polished renders in `metal`.
+pub fn outputs(metal: &Path) -> Vec<(&'static str, PathBuf, [Option<ChannelSpec>; 3])> {
+    let glossy = |source: &str| Some(ChannelSpec { source: source.to_string(), pass: "Glossy".to_string(), component: Component::Luma });
+
+    vec![("metal", metal.to_path_buf(), [glossy(SOURCES[0]), glossy(SOURCES[1]), None])]
+}
@@ -0,0 +1,30 @@
+use clap::Parser;
+use compositor::Spec;
+use pipeline::stages::{CompositeArgs, composite};
+use std::path::PathBuf;
+use util::CommonArgs;
+
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct CliArgs {
+
+    /// JSON description of the sources to read and the maps to write
+    #[clap(long, parse(from_os_str))]
+    spec: PathBuf,
+
+    #[clap(flatten)]
+    composite: CompositeArgs,
+
+    #[clap(flatten)]
+    common: CommonArgs,
+}
+
+
+fn main() {
+    let args = CliArgs::parse();
+
+    let spec = Spec::from_file(&args.spec);
+
+    composite(&spec, &args.composite, &args.common);
+}
@@ -0,0 +1,22 @@
+use clap::Parser;
+use pipeline::stages::{DepthArgs, depth};
+use util::CommonArgs;
+
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct CliArgs {
+
+    #[clap(flatten)]
+    depth: DepthArgs,
+
+    #[clap(flatten)]
+    common: CommonArgs,
+}
+
+
+fn main() {
+    let args = CliArgs::parse();
+
+    depth(&args.depth, &args.common);
+}
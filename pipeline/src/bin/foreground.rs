@@ -0,0 +1,22 @@
+use clap::Parser;
+use pipeline::stages::{ForegroundArgs, foreground};
+use util::CommonArgs;
+
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct CliArgs {
+
+    #[clap(flatten)]
+    foreground: ForegroundArgs,
+
+    #[clap(flatten)]
+    common: CommonArgs,
+}
+
+
+fn main() {
+    let args = CliArgs::parse();
+
+    foreground(&args.foreground, &args.common);
+}
@@ -0,0 +1,22 @@
+use clap::Parser;
+use pipeline::stages::{MatteArgs, matte};
+use util::CommonArgs;
+
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct CliArgs {
+
+    #[clap(flatten)]
+    matte: MatteArgs,
+
+    #[clap(flatten)]
+    common: CommonArgs,
+}
+
+
+fn main() {
+    let args = CliArgs::parse();
+
+    matte(&args.matte, &args.common);
+}
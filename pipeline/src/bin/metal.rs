@@ -0,0 +1,22 @@
+use clap::Parser;
+use pipeline::stages::{MetalArgs, metal};
+use util::CommonArgs;
+
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct CliArgs {
+
+    #[clap(flatten)]
+    metal: MetalArgs,
+
+    #[clap(flatten)]
+    common: CommonArgs,
+}
+
+
+fn main() {
+    let args = CliArgs::parse();
+
+    metal(&args.metal, &args.common);
+}
@@ -0,0 +1,281 @@
+use clap::Args;
+use compositor::{ConfigOptions, Configuration, frame_path, get_configurations};
+use depth::{flicker, neighbours};
+use matte::{get_index_map, read_matte_exr, unknown_ids, unpack_index};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use util::{CommonArgs, thread_pool};
+use crate::project::{Project, level_path};
+use crate::run::parse_frames;
+use crate::verify::frame_ranges;
+
+
+/// Options of `pipeline check`.
+#[derive(Args, Debug)]
+pub struct CheckArgs {
+
+    /// JSON description of where every stage reads and writes
+    #[clap(long, parse(from_os_str))]
+    pub project: PathBuf,
+
+    /// Products to check: `zmask`, `index`, `matte` or the name of a spec output; all if omitted
+    #[clap(long, value_delimiter = ',')]
+    pub products: Vec<String>,
+
+    /// Levels to check, e.g. `0,1,2`; the shared `--level` if omitted
+    #[clap(long, value_delimiter = ',')]
+    pub levels: Vec<u32>,
+
+    /// Frames counted from 0, e.g. `0-23` or `5`; all 144 if omitted
+    #[clap(long)]
+    pub frames: Option<String>,
+
+    /// Share of pixels whose histogram bin may change between neighbouring frames
+    #[clap(long, default_value = "0.25")]
+    pub max_histogram_change: f32,
+
+    /// Share of the pixels covered by an object that may be claimed by no zmask layer
+    #[clap(long, default_value = "0.001")]
+    pub max_unclaimed: f32,
+
+    /// Share of the covered zmask pixels whose owner may flip for a single frame of a turn
+    #[clap(long, default_value = "0.001")]
+    pub max_flicker: f32,
+
+    /// Also write the anomalies to this JSON file
+    #[clap(long, parse(from_os_str))]
+    pub report: Option<PathBuf>,
+}
+
+
+/// Something wrong with one map.
+#[derive(Debug, Serialize)]
+pub struct Anomaly {
+    pub product: String,
+    /// Configuration, empty for maps that don't depend on it.
+    pub config: String,
+    pub level: u32,
+    pub frame: u32,
+    pub problem: String,
+}
+
+
+/// Maps of one product and configuration across frames, checked in frame order.
+struct Sequence {
+    product: String,
+    config: String,
+    level: u32,
+    frames: Vec<(u32, PathBuf)>,
+    /// Maps of the frames before and after each frame in its turn.
+    neighbours: Vec<(PathBuf, PathBuf)>,
+}
+
+
+const BINS: usize = 64;
+
+/// Share of values in each of `BINS` equal bins over the full code range.
+fn histogram(values: &[u16]) -> [f32; BINS] {
+    let mut counts = [0_usize; BINS];
+    for x in values {
+        counts[*x as usize * BINS / 65536] += 1;
+    }
+    counts.map(|x| x as f32 / values.len().max(1) as f32)
+}
+
+
+/// Anomalies of the maps in `sequence`. `objects` holds, per frame, which pixels are covered by an
+/// object according to the index map, to find zmask pixels claimed by no layer.
+fn check_sequence(sequence: &Sequence, objects: &HashMap<(u32, u32), Vec<bool>>, args: &CheckArgs) -> Vec<Anomaly> {
+    let mut anomalies = Vec::new();
+    let mut flag = |frame: u32, problem: String| anomalies.push(Anomaly {
+        product: sequence.product.clone(),
+        config: sequence.config.clone(),
+        level: sequence.level,
+        frame,
+        problem,
+    });
+
+    let mut previous: Option<(u32, [f32; BINS])> = None;
+    for ((frame, path), (previous_path, next_path)) in sequence.frames.iter().zip(&sequence.neighbours) {
+        let image = match image::open(path) {
+            Ok(x) => x,
+            Err(_) if !path.exists() => {
+                flag(*frame, "missing".to_string());
+                continue;
+            }
+            Err(e) => {
+                flag(*frame, format!("unreadable ({})", e));
+                continue;
+            }
+        };
+
+        let values = image.to_rgb16().into_raw();
+        if values.iter().all(|x| *x == 0) {
+            flag(*frame, "all zero".to_string());
+        } else if values.iter().all(|x| *x == u16::MAX) {
+            flag(*frame, "all at the maximum code".to_string());
+        }
+
+        // Views repeat every 24 frames, so only neighbours within a turn should look alike
+        let current = histogram(&values);
+        if let Some((previous_frame, previous)) = previous {
+            let change = previous.iter().zip(&current).map(|(a, b)| (a - b).abs()).sum::<f32>() / 2.0;
+            if previous_frame + 1 == *frame && frame % 24 != 0 && change > args.max_histogram_change {
+                flag(*frame, format!("histogram changed by {:.0}% since frame {}", change * 100.0, previous_frame));
+            }
+        }
+        previous = Some((*frame, current));
+
+        if sequence.product == "zmask" {
+            let zmask = image.to_rgb8().into_raw();
+            if zmask.iter().any(|x| *x > 1) {
+                flag(*frame, "values other than 0 and 1".to_string());
+            }
+            if zmask.chunks(3).any(|x| x.iter().filter(|v| **v > 0).count() > 1) {
+                flag(*frame, "pixels claimed by several layers".to_string());
+            }
+            // Neighbours outside the frames checked are still read when they exist
+            let rgba = image.to_rgba8().into_raw();
+            if let (Ok(previous), Ok(next)) = (image::open(previous_path), image::open(next_path)) {
+                let flicker = flicker(&previous.to_rgba8().into_raw(), &rgba, &next.to_rgba8().into_raw());
+                if flicker > args.max_flicker {
+                    flag(*frame, format!("ownership flickers on {:.2}% of the pixels", flicker * 100.0));
+                }
+            }
+            if let Some(objects) = objects.get(&(sequence.level, *frame)) {
+                let covered = objects.iter().filter(|x| **x).count();
+                let unclaimed = zmask.chunks(3).zip(objects).filter(|(x, covered)| **covered && x == &[0, 0, 0]).count();
+                if covered > 0 && unclaimed as f32 / covered as f32 > args.max_unclaimed {
+                    flag(*frame, format!("{} object pixels claimed by no layer", unclaimed));
+                }
+            }
+        }
+
+        if sequence.product == "index" {
+            let index = image.to_rgb8().into_raw();
+            if index.chunks(3).any(|x| unpack_index([x[0], x[1], x[2]]).iter().any(|id| *id as usize >= get_index_map().len())) {
+                flag(*frame, "unknown material IDs in the index map".to_string());
+            }
+        }
+    }
+    anomalies
+}
+
+
+/// Scans the maps a project produced for blank or clipped frames, bad or flickering zmasks,
+/// unknown material IDs and sudden changes between neighbouring frames, and prints a table of them.
+pub fn run(args: &CheckArgs, common: &CommonArgs) {
+    let project = Project::from_file(&args.project);
+    let spec = &project.spec;
+    let base = common.base_resolution();
+    let levels = match args.levels.is_empty() {
+        true => vec![common.level()],
+        false => args.levels.clone(),
+    };
+    let frames = parse_frames(&args.frames);
+
+    let wanted = |product: &str| args.products.is_empty() || args.products.iter().any(|x| x == product);
+    for product in &args.products {
+        if !["zmask", "index", "matte"].contains(&product.as_str()) && !spec.outputs.iter().any(|x| x.name == *product) {
+            panic!("Error: unknown product '{}'", product);
+        }
+    }
+
+    let mut configs: Vec<Configuration> = Vec::new();
+    get_configurations(&mut configs, ConfigOptions::default());
+
+    let mut sequences = Vec::new();
+    for &level in &levels {
+        let mut add = |product: &str, config: &str, path: &dyn Fn(u32) -> PathBuf| sequences.push(Sequence {
+            product: product.to_string(),
+            config: config.to_string(),
+            level,
+            frames: frames.iter().map(|&frame| (frame, path(frame))).collect(),
+            neighbours: frames.iter().map(|&frame| {
+                let (previous, next) = neighbours(frame as usize);
+                (path(previous as u32), path(next as u32))
+            }).collect(),
+        });
+
+        if let Some(mattes) = &project.mattes {
+            for (product, dir) in [("index", &mattes.index), ("matte", &mattes.matte)] {
+                if wanted(product) {
+                    add(product, "", &|frame| level_path(dir, base, level, frame, "webp"));
+                }
+            }
+        }
+        for (config, _, _, _) in &configs {
+            if wanted("zmask") {
+                add("zmask", config, &|frame| frame_path(&project.zmask, base, config, level, frame, "webp"));
+            }
+            for output in spec.outputs.iter().filter(|x| wanted(&x.name)) {
+                add(&output.name, config, &|frame| frame_path(&output.dir, base, config, level, frame, output.format.extension()));
+            }
+        }
+    }
+
+    let pool = thread_pool(common.jobs);
+
+    // Which pixels hold an object, from the rank 0 ID of the index maps, and unknown IDs in the
+    // cryptomattes, which the index maps can't show since they are written as VOID
+    let mut objects: HashMap<(u32, u32), Vec<bool>> = HashMap::new();
+    let mut anomalies: Vec<Anomaly> = Vec::new();
+    if let Some(mattes) = &project.mattes {
+        let arr = get_index_map();
+        let keys = levels.iter().flat_map(|&level| frames.iter().map(move |&frame| (level, frame))).collect::<Vec<_>>();
+        let results: Vec<(Option<Vec<bool>>, Option<Anomaly>)> = pool.install(|| keys.par_iter().map(|&(level, frame)| {
+            let covered = image::open(level_path(&mattes.index, base, level, frame, "webp")).ok().map(|x| {
+                x.to_rgb8().into_raw().chunks(3).map(|x| !matches!(unpack_index([x[0], x[1], x[2]])[0], 0 | 31)).collect()
+            });
+
+            let crypto = level_path(&mattes.crypto, base, level, frame, "exr");
+            let unknown = match wanted("index") && crypto.exists() {
+                true => unknown_ids(&arr, &read_matte_exr(&crypto, base * 2_u32.pow(level))),
+                false => 0,
+            };
+            let anomaly = (unknown > 0).then(|| Anomaly {
+                product: "index".to_string(),
+                config: String::new(),
+                level,
+                frame,
+                problem: format!("{} pixels with unknown material IDs in the cryptomatte", unknown),
+            });
+            (covered, anomaly)
+        }).collect());
+
+        for (key, (covered, anomaly)) in keys.into_iter().zip(results) {
+            if let Some(covered) = covered {
+                objects.insert(key, covered);
+            }
+            anomalies.extend(anomaly);
+        }
+    }
+
+    let found: Vec<Vec<Anomaly>> = pool.install(|| sequences.par_iter().map(|x| check_sequence(x, &objects, args)).collect());
+    anomalies.extend(found.into_iter().flatten());
+
+    if let Some(path) = &args.report {
+        fs::write(path, serde_json::to_string_pretty(&anomalies).unwrap()).unwrap();
+    }
+
+    let maps = sequences.iter().map(|x| x.frames.len()).sum::<usize>();
+    if anomalies.is_empty() {
+        println!("No anomalies in {} maps", maps);
+        return;
+    }
+
+    let mut rows: BTreeMap<(&str, &str, u32, &str), Vec<u32>> = BTreeMap::new();
+    for x in &anomalies {
+        rows.entry((&x.product, &x.config, x.level, &x.problem)).or_default().push(x.frame);
+    }
+    println!("{:<16} {:<40} {:>5}  {:<24} problem", "product", "configuration", "level", "frames");
+    for ((product, config, level, problem), mut frames) in rows {
+        frames.sort();
+        println!("{:<16} {:<40} {:>5}  {:<24} {}", product, config, level, frame_ranges(&frames), problem);
+    }
+    println!("{} anomalies in {} maps", anomalies.len(), maps);
+    std::process::exit(1);
+}
@@ -0,0 +1,4 @@
+//! Stages of the pipeline with their command line options, shared by `pipeline` and the
+//! standalone binaries of each stage.
+
+pub mod stages;
@@ -0,0 +1,100 @@
+use check::CheckArgs;
+use clap::{Args, Parser, Subcommand};
+use pipeline::stages::{self, DepthArgs, ForegroundArgs, MatteArgs, MetalArgs};
+use run::RunArgs;
+use std::path::{Path, PathBuf};
+use util::{CommonArgs, FileEntry, Manifest};
+use verify::VerifyArgs;
+
+mod check;
+mod project;
+mod run;
+mod verify;
+
+
+/// Runs any stage of the pipeline with shared options.
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct CliArgs {
+
+    /// JSON file with defaults for the shared options
+    #[clap(long, parse(from_os_str), global = true)]
+    config: Option<PathBuf>,
+
+    #[clap(flatten)]
+    common: CommonArgs,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Bring products up to date, running the stages they depend on
+    Run(RunArgs),
+    /// Front, rear and upper ownership masks from the depth renders
+    Depth(DepthArgs),
+    /// Cryptomatte index and matte maps
+    Matte(MatteArgs),
+    /// Foreground light maps
+    Light(ForegroundArgs),
+    /// Metal maps
+    Metal(MetalArgs),
+    /// Convert OCIO LUTs to binary half float tables
+    Lut(LutArgs),
+    /// Check that every input EXR exists and has the resolution, passes and sample types the stages read
+    Verify(VerifyArgs),
+    /// Scan written maps for blank or clipped frames, bad zmasks, unknown material IDs and jumps between frames
+    Check(CheckArgs),
+}
+
+#[derive(Args, Debug)]
+struct LutArgs {
+
+    /// 1D LUT written to `filmic_to_0-70_1-03.bin`
+    #[clap(long, parse(from_os_str))]
+    lut1d: Option<PathBuf>,
+
+    /// 65^3 3D LUT written to `filmic_desat65cube.bin`
+    #[clap(long, parse(from_os_str))]
+    lut3d: Option<PathBuf>,
+}
+
+
+
+fn main() {
+    let args = CliArgs::parse();
+
+    let common = match &args.config {
+        Some(path) => args.common.or(CommonArgs::from_file(path)),
+        None => args.common,
+    };
+
+    match args.command {
+        Command::Run(x) => run::run(&x, &common),
+        Command::Depth(x) => stages::depth(&x, &common),
+        Command::Matte(x) => stages::matte(&x, &common),
+        Command::Light(x) => stages::foreground(&x, &common),
+        Command::Metal(x) => stages::metal(&x, &common),
+        Command::Lut(x) => {
+            let mut written = Vec::new();
+            if let Some(path) = x.lut1d {
+                lut::process_lut1d(path.to_str().unwrap()).unwrap();
+                written.push("filmic_to_0-70_1-03.bin");
+            }
+            if let Some(path) = x.lut3d {
+                lut::process_lut3d(path.to_str().unwrap()).unwrap();
+                written.push("filmic_desat65cube.bin");
+            }
+            if let Some(manifest) = &common.manifest {
+                Manifest::update(manifest, |manifest| {
+                    for name in written {
+                        manifest.luts.insert(name.to_string(), FileEntry::new(Path::new(name)).unwrap());
+                    }
+                });
+            }
+        }
+        Command::Verify(x) => verify::run(&x, &common),
+        Command::Check(x) => check::run(&x, &common),
+    }
+}
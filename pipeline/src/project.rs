@@ -0,0 +1,56 @@
+use compositor::Spec;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+
+/// Where every stage reads and writes, for `pipeline run`.
+///
+/// ```json
+/// {
+///   "depth": "/renders/depth",
+///   "plane": "/renders/plane",
+///   "zmask": "/maps/zmask",
+///   "mattes": { "crypto": "/renders/crypto", "index": "/maps/index", "matte": "/maps/matte" },
+///   "spec": { "sources": { ... }, "outputs": [ ... ] }
+/// }
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Project {
+    /// Depth renders of each assembly, `{depth}/{base}/{assembly}/{level}/{frame}.exr`.
+    pub depth: PathBuf,
+    /// Depth of the clipping plane, `{plane}/{base}/{level}/{frame}.exr`.
+    pub plane: PathBuf,
+    /// Zmasks of each configuration, `{zmask}/{base}/{config}/{level}/{frame}.webp`.
+    pub zmask: PathBuf,
+    #[serde(default)]
+    pub mattes: Option<Mattes>,
+    /// Maps composited from the renders and zmasks, see `compositor::Spec`.
+    pub spec: Spec,
+}
+
+/// Cryptomatte renders, `{crypto}/{base}/{level}/{frame}.exr`, and the maps made from them,
+/// `{dir}/{base}/{level}/{frame}.webp`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Mattes {
+    pub crypto: PathBuf,
+    pub index: PathBuf,
+    pub matte: PathBuf,
+}
+
+impl Project {
+    pub fn from_file(path: &Path) -> Self {
+        let text = fs::read_to_string(path).unwrap_or_else(|e| panic!("Error: cannot read project {:?} ({})", path, e));
+        let project: Project = serde_json::from_str(&text).unwrap_or_else(|e| panic!("Error: invalid project {:?} ({})", path, e));
+        project.spec.validate();
+        project
+    }
+}
+
+
+/// `{dir}/{base_resolution}/{level}/{frame}.{extension}`, for files that don't vary by configuration.
+pub fn level_path(dir: &Path, base_resolution: u32, level: u32, frame: u32, extension: &str) -> PathBuf {
+    dir.join(format!("{}/{}/{:0>4}", base_resolution, level, 121 + frame)).with_extension(extension)
+}
@@ -0,0 +1,281 @@
+use clap::Args;
+use compositor::{ConfigOptions, Configuration, frame_path, get_configurations, output_params, write_stale_maps};
+use depth::{TURN, ZmaskJob, print_flicker, stabilize_zmasks, zmask_params};
+use matte::{MatteJob, matte_params};
+use pipeline::stages::{FRAMES, composite_jobs};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::PathBuf;
+use util::{BuildDb, CommonArgs};
+use crate::project::{Project, level_path};
+use crate::verify;
+
+
+/// Options of `pipeline run`.
+#[derive(Args, Debug)]
+pub struct RunArgs {
+
+    /// JSON description of where every stage reads and writes
+    #[clap(long, parse(from_os_str))]
+    pub project: PathBuf,
+
+    /// Products to bring up to date: `zmask`, `matte` or the name of a spec output; all if omitted
+    #[clap(long, value_delimiter = ',')]
+    pub products: Vec<String>,
+
+    /// Frames counted from 0, e.g. `0-23` or `5`; all 144 if omitted
+    #[clap(long)]
+    pub frames: Option<String>,
+
+    /// Only report what is out of date
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Resolve zmask ownership that flips for a single frame where the sub-assemblies' depths are
+    /// within this fraction of each other, e.g. `0.01`; zmasks are then made a whole turn at a time
+    #[clap(long)]
+    pub temporal_margin: Option<f32>,
+
+    /// Upper bound (MiB) on decoded sub-assemblies kept in host memory, split between devices; unbounded if omitted
+    #[clap(long)]
+    pub memory_budget: Option<u64>,
+
+    /// Upper bound (MiB) on sub-assemblies kept resident on each device; unbounded if omitted
+    #[clap(long)]
+    pub gpu_memory_budget: Option<u64>,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Stage {
+    Depth,
+    Matte,
+    Composite(String),
+}
+
+/// One output file set of a stage, for one configuration and frame.
+struct Task {
+    stage: Stage,
+    /// Configuration name, empty for stages that don't depend on it.
+    config: String,
+    frame: u32,
+    inputs: Vec<PathBuf>,
+    outputs: Vec<PathBuf>,
+    /// Tool version and settings the outputs depend on.
+    params: String,
+    /// Tasks producing some of `inputs`, always added before this one.
+    deps: Vec<usize>,
+    /// Tasks sharing a group are run together, e.g. the zmasks of a turn for the temporal pass.
+    group: Option<(String, u32)>,
+}
+
+#[derive(Default)]
+struct Dag {
+    tasks: Vec<Task>,
+}
+
+impl Dag {
+    fn add(&mut self, task: Task) -> usize {
+        self.tasks.push(task);
+        self.tasks.len() - 1
+    }
+
+    /// Tasks to run: those with outputs missing or built from other inputs or settings, the rest
+    /// of their group, and everything downstream of them.
+    fn stale(&self, overwrite: bool, db: &mut BuildDb) -> Vec<bool> {
+        let mut groups: HashSet<&(String, u32)> = HashSet::new();
+        loop {
+            let mut stale: Vec<bool> = Vec::with_capacity(self.tasks.len());
+            for task in &self.tasks {
+                let x = overwrite
+                    || task.deps.iter().any(|&i| stale[i])
+                    || task.group.as_ref().is_some_and(|x| groups.contains(x))
+                    || !task.outputs.iter().all(|x| db.is_current(x, &task.inputs, &task.params));
+                stale.push(x);
+            }
+
+            // Repeat with the groups of the stale tasks until no more join
+            let stale_groups = self.tasks.iter().zip(&stale).filter(|x| *x.1).filter_map(|x| x.0.group.as_ref()).collect::<HashSet<_>>();
+            if stale_groups.is_subset(&groups) {
+                return stale;
+            }
+            groups = stale_groups;
+        }
+    }
+}
+
+
+/// Frames of `--frames`, a range `first-last` or a single frame, every frame if omitted.
+pub fn parse_frames(frames: &Option<String>) -> Vec<u32> {
+    let parse = |x: &str| x.trim().parse::<u32>().unwrap_or_else(|_| panic!("Error: invalid frame '{}'", x));
+    let (first, last) = match frames.as_deref().map(|x| x.split_once('-')) {
+        None => return (0..FRAMES as u32).collect(),
+        Some(Some((first, last))) => (parse(first), parse(last)),
+        Some(None) => {
+            let frame = parse(frames.as_ref().unwrap());
+            (frame, frame)
+        }
+    };
+
+    if first > last {
+        panic!("Error: frames '{}' end before they start", frames.as_ref().unwrap());
+    }
+    if last >= FRAMES as u32 {
+        panic!("Error: frames '{}' go past the last frame, {}", frames.as_ref().unwrap(), FRAMES - 1);
+    }
+    (first..=last).collect()
+}
+
+
+/// Brings the requested products up to date for the frames, running depth before the maps that
+/// read its zmasks and skipping everything that is newer than its inputs.
+pub fn run(args: &RunArgs, common: &CommonArgs) {
+    let project = Project::from_file(&args.project);
+    let spec = &project.spec;
+    let (base, level) = (common.base_resolution(), common.level());
+    let frames = parse_frames(&args.frames);
+
+    let wanted = |product: &str| args.products.is_empty() || args.products.iter().any(|x| x == product);
+    let resolution = common.resolution();
+    let outputs = spec.outputs.iter().filter(|x| wanted(&x.name)).map(|x| (x, output_params(spec, x, resolution))).collect::<Vec<_>>();
+    for product in &args.products {
+        if !["zmask", "matte"].contains(&product.as_str()) && !spec.outputs.iter().any(|x| x.name == *product) {
+            panic!("Error: unknown product '{}'", product);
+        }
+    }
+
+    let mut configs: Vec<Configuration> = Vec::new();
+    get_configurations(&mut configs, ConfigOptions::default());
+
+    // The temporal pass compares each zmask with its neighbours, so it needs whole turns
+    let margin = args.temporal_margin;
+    let depth_frames = match margin {
+        Some(_) => {
+            let turns = frames.iter().map(|x| x / TURN as u32).collect::<BTreeSet<_>>();
+            turns.into_iter().flat_map(|x| x * TURN as u32..(x + 1) * TURN as u32).collect()
+        }
+        None => frames.clone(),
+    };
+
+    let mut dag = Dag::default();
+    for &frame in &depth_frames {
+        let requested = frames.contains(&frame);
+        if let (true, true, Some(mattes)) = (requested, wanted("matte"), &project.mattes) {
+            dag.add(Task {
+                stage: Stage::Matte,
+                config: String::new(),
+                frame,
+                inputs: vec![level_path(&mattes.crypto, base, level, frame, "exr")],
+                outputs: vec![
+                    level_path(&mattes.index, base, level, frame, "webp"),
+                    level_path(&mattes.matte, base, level, frame, "webp"),
+                ],
+                params: matte_params(resolution),
+                deps: vec![],
+                group: None,
+            });
+        }
+
+        if !wanted("zmask") && outputs.is_empty() {
+            continue;
+        }
+
+        for (config, front, rear, upper) in &configs {
+            let zmask = frame_path(&project.zmask, base, config, level, frame, "webp");
+            let mut inputs = [front, rear, upper].map(|x| frame_path(&project.depth, base, x, level, frame, "exr")).to_vec();
+            inputs.push(level_path(&project.plane, base, level, frame, "exr"));
+            let depth = dag.add(Task {
+                stage: Stage::Depth,
+                config: config.clone(),
+                frame,
+                inputs,
+                outputs: vec![zmask.clone()],
+                params: zmask_params(frame as usize, resolution, margin),
+                deps: vec![],
+                group: margin.map(|_| (config.clone(), frame / TURN as u32)),
+            });
+            if !requested {
+                continue;
+            }
+
+            for (output, params) in &outputs {
+                let mut inputs = vec![zmask.clone()];
+                for source in output.channels.iter().flatten().map(|x| &x.source).collect::<BTreeSet<_>>() {
+                    inputs.extend([front, rear, upper].iter().flat_map(|x| spec.files(source, &frame_path(&spec.sources[source], base, x, level, frame, "exr"))));
+                }
+
+                dag.add(Task {
+                    stage: Stage::Composite(output.name.clone()),
+                    config: config.clone(),
+                    frame,
+                    inputs,
+                    outputs: vec![frame_path(&output.dir, base, config, level, frame, output.format.extension())],
+                    params: params.clone(),
+                    deps: vec![depth],
+                    group: None,
+                });
+            }
+        }
+    }
+
+    let mut db = BuildDb::new();
+    let stale = dag.stale(common.overwrite, &mut db);
+    let mut counts: HashMap<&Stage, (usize, usize)> = HashMap::new();
+    for (task, stale) in dag.tasks.iter().zip(&stale) {
+        let count = counts.entry(&task.stage).or_default();
+        count.0 += *stale as usize;
+        count.1 += 1;
+    }
+    let mut stages = counts.into_iter().collect::<Vec<_>>();
+    stages.sort();
+    for (stage, (pending, total)) in stages {
+        println!("{:<24} {:>7} of {:>7} out of date", format!("{:?}", stage), pending, total);
+    }
+
+    let pending = dag.tasks.iter().zip(&stale).filter(|(_, x)| **x).map(|(x, _)| x).collect::<Vec<_>>();
+
+    // Check the EXRs the pending tasks read up front rather than failing halfway through
+    let reads = pending.iter().flat_map(|x| &x.inputs).collect::<HashSet<_>>();
+    let inputs = verify::inputs(&project, base, &[level], &depth_frames).into_iter().filter(|x| reads.contains(&x.path)).collect::<Vec<_>>();
+    let bad = verify::report(&inputs, base, false, common);
+
+    // Outputs adopted by the checks above are recorded on the next real run
+    if args.dry_run {
+        return;
+    }
+    if bad > 0 {
+        panic!("Error: {} inputs are missing or corrupt", bad);
+    }
+
+    let zmasks = pending.iter().filter(|x| x.stage == Stage::Depth).map(|x| ZmaskJob {
+        frame: x.frame as usize,
+        config: x.config.clone(),
+        inputs: x.inputs.clone().try_into().unwrap(),
+        output: x.outputs[0].clone(),
+    }).collect::<Vec<_>>();
+    if !zmasks.is_empty() {
+        depth::write_zmasks(&zmasks, resolution, common, &mut db);
+        if let Some(margin) = margin {
+            print_flicker(&stabilize_zmasks(&zmasks, resolution, margin, common, &mut db));
+        }
+    }
+
+    let mattes = pending.iter().filter(|x| x.stage == Stage::Matte).map(|x| MatteJob {
+        frame: x.frame as usize,
+        input: x.inputs[0].clone(),
+        index: x.outputs[0].clone(),
+        matte: x.outputs[1].clone(),
+    }).collect::<Vec<_>>();
+    if !mattes.is_empty() {
+        matte::write_mattes(&mattes, resolution, common, &mut db);
+    }
+    db.save();
+
+    // The compositor skips the configurations that are up to date on its own
+    let composite_frames = pending.iter().filter(|x| matches!(x.stage, Stage::Composite(_))).map(|x| x.frame).collect::<BTreeSet<_>>();
+    let mut spec = spec.clone();
+    spec.outputs.retain(|x| wanted(&x.name));
+    let jobs = composite_frames.into_iter().flat_map(|frame| composite_jobs(&spec, frame, &project.zmask, common)).collect::<Vec<_>>();
+    if !jobs.is_empty() {
+        write_stale_maps(&spec, jobs, args.memory_budget, args.gpu_memory_budget, common, &mut db);
+    }
+}
@@ -0,0 +1,266 @@
+use clap::Args;
+use compositor::{ChannelSpec, CompositeJob, ConfigOptions, Configuration, LogEncoding, Output, OutputFormat, Spec, WorkingSpace, frame_path, get_configurations, write_stale_maps};
+use depth::{ZmaskJob, write_stale_zmasks};
+use matte::{MatteJob, write_stale_mattes};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use util::{BuildDb, CommonArgs, frame_files};
+
+
+/// Frames of a turntable, each directory of renders holds one per frame.
+pub const FRAMES: usize = 144;
+
+
+/// Options of the depth mask generator, `pipeline depth` or `depth`.
+#[derive(Args, Debug)]
+pub struct DepthArgs {
+
+    /// Resolution of the renders; `base_resolution * 2^level`, or read from the first render, if omitted
+    #[clap(long)]
+    pub resolution: Option<u32>,
+
+    #[clap(long, parse(from_os_str))]
+    pub zfront: PathBuf,
+
+    #[clap(long, parse(from_os_str))]
+    pub zrear: PathBuf,
+
+    #[clap(long, parse(from_os_str))]
+    pub zupper: PathBuf,
+
+    #[clap(long, parse(from_os_str))]
+    pub zplane: PathBuf,
+
+    #[clap(long, parse(from_os_str))]
+    pub zmask: PathBuf,
+
+    /// Resolve ownership that flips for a single frame where the sub-assemblies' depths are within
+    /// this fraction of each other, e.g. `0.01`, and report the flicker per frame
+    #[clap(long)]
+    pub temporal_margin: Option<f32>,
+}
+
+
+/// Options of the cryptomatte index and matte generator, `pipeline matte` or `matte`.
+#[derive(Args, Debug)]
+pub struct MatteArgs {
+
+    /// Resolution of the renders; `base_resolution * 2^level`, or read from the first render, if omitted
+    #[clap(long)]
+    pub resolution: Option<u32>,
+
+    #[clap(long, parse(from_os_str))]
+    pub input: PathBuf,
+
+    #[clap(long, parse(from_os_str))]
+    pub index: PathBuf,
+
+    #[clap(long, parse(from_os_str))]
+    pub matte: PathBuf,
+}
+
+
+/// Options of every compositing stage.
+#[derive(Args, Debug)]
+pub struct CompositeArgs {
+
+    #[clap(long)]
+    pub frame: u32,
+
+    #[clap(long, parse(from_os_str))]
+    pub zmask: PathBuf,
+
+    /// Upper bound (MiB) on decoded sub-assemblies kept in host memory, split between devices; unbounded if omitted
+    #[clap(long)]
+    pub memory_budget: Option<u64>,
+
+    /// Upper bound (MiB) on sub-assemblies kept resident on each device; unbounded if omitted
+    #[clap(long)]
+    pub gpu_memory_budget: Option<u64>,
+}
+
+
+/// How the map compositors read the renders and encode their outputs.
+#[derive(Args, Debug)]
+pub struct MapArgs {
+
+    /// JSON file with the log encoding parameters; the standard encoding if omitted
+    #[clap(long, parse(from_os_str))]
+    pub encoding: Option<PathBuf>,
+
+    /// How to store the maps; 16-bit formats avoid banding on polished surfaces
+    #[clap(long, arg_enum, default_value = "webp")]
+    pub format: OutputFormat,
+
+    /// Colour space the renders are converted to before compositing
+    #[clap(long, arg_enum, default_value = "rec709")]
+    pub working_space: WorkingSpace,
+
+    /// Weights of R, G and B in the luma of a pass; derived from the working space if omitted
+    #[clap(long, value_delimiter = ',', number_of_values = 3)]
+    pub luma_weights: Option<Vec<f32>>,
+
+    /// The renders are one file per pass, `{frame}.{pass}.exr`, rather than multi-pass EXRs
+    #[clap(long)]
+    pub per_pass_files: bool,
+
+    /// Also write the product's coverage from the zmask as alpha, for any background in the viewer
+    #[clap(long)]
+    pub alpha: bool,
+}
+
+impl MapArgs {
+    /// Spec reading `sources` and writing each `(name, dir, channels)` of `outputs` with these
+    /// options.
+    pub fn spec(&self, sources: BTreeMap<String, PathBuf>, outputs: Vec<(&str, PathBuf, [Option<ChannelSpec>; 3])>) -> Spec {
+        let encoding = self.encoding.as_ref().map(|x| LogEncoding::from_file(x)).unwrap_or_default();
+
+        Spec {
+            per_pass_files: if self.per_pass_files { sources.keys().cloned().collect() } else { BTreeSet::new() },
+            sources,
+            working_space: self.working_space,
+            luma_weights: self.luma_weights.as_ref().map(|x| x.as_slice().try_into().unwrap()),
+            outputs: outputs.into_iter().map(|(name, dir, channels)| Output {
+                name: name.to_string(),
+                dir,
+                channels,
+                encoding,
+                format: self.format,
+                lut: None,
+                alpha: self.alpha,
+            }).collect(),
+        }
+    }
+}
+
+
+/// Options of the foreground map compositor, `pipeline light` or `foreground`.
+#[derive(Args, Debug)]
+pub struct ForegroundArgs {
+
+    #[clap(long, parse(from_os_str))]
+    pub foreground: PathBuf,
+
+    #[clap(long, parse(from_os_str))]
+    pub light: PathBuf,
+
+    /// Also write the diffuse and glossy passes in colour, to `{colour}/diffuse` and `{colour}/glossy`
+    #[clap(long, parse(from_os_str))]
+    pub colour: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub maps: MapArgs,
+
+    #[clap(flatten)]
+    pub composite: CompositeArgs,
+}
+
+
+/// Options of the metal map compositor, `pipeline metal` or `metal`.
+#[derive(Args, Debug)]
+pub struct MetalArgs {
+
+    #[clap(long, parse(from_os_str))]
+    pub raw: PathBuf,
+
+    #[clap(long, parse(from_os_str))]
+    pub polish: PathBuf,
+
+    #[clap(long, parse(from_os_str))]
+    pub metal: PathBuf,
+
+    #[clap(flatten)]
+    pub maps: MapArgs,
+
+    #[clap(flatten)]
+    pub composite: CompositeArgs,
+}
+
+
+/// `{dir}/{frame}.webp`, frames numbered from 121.
+fn map_path(dir: &Path, frame: usize) -> PathBuf {
+    dir.join(format!("{:0>4}", (121 + frame).to_string())).with_extension("webp")
+}
+
+
+/// Writes the zmasks of the depth renders in the directories of `args` that are out of date.
+pub fn depth(args: &DepthArgs, common: &CommonArgs) {
+    let files = [&args.zfront, &args.zrear, &args.zupper, &args.zplane].map(|dir| frame_files(dir, FRAMES));
+    let size = common.resolution_or_infer(args.resolution, &files[0][0]);
+
+    let jobs = (0..FRAMES).map(|frame| ZmaskJob {
+        frame,
+        config: String::new(),
+        inputs: files.each_ref().map(|x| x[frame].clone()),
+        output: map_path(&args.zmask, frame),
+    }).collect::<Vec<_>>();
+
+    write_stale_zmasks(jobs, size, args.temporal_margin, common, &mut BuildDb::new());
+}
+
+
+/// Writes the index and matte maps of the cryptomattes in `args.input` that are out of date.
+pub fn matte(args: &MatteArgs, common: &CommonArgs) {
+    let files = frame_files(&args.input, FRAMES);
+    let size = common.resolution_or_infer(args.resolution, &files[0]);
+
+    let jobs = files.into_iter().enumerate().map(|(frame, input)| MatteJob {
+        frame,
+        input,
+        index: map_path(&args.index, frame),
+        matte: map_path(&args.matte, frame),
+    }).collect::<Vec<_>>();
+
+    write_stale_mattes(jobs, size, common, &mut BuildDb::new());
+}
+
+
+/// Jobs compositing every configuration of `frame` from the zmasks in `zmask` and the renders in
+/// the spec's source directories.
+pub fn composite_jobs(spec: &Spec, frame: u32, zmask: &Path, common: &CommonArgs) -> Vec<CompositeJob> {
+    let (base, level) = (common.base_resolution(), common.level());
+    let path = |dir: &Path, name: &str, extension: &str| frame_path(dir, base, name, level, frame, extension);
+
+    let mut configs: Vec<Configuration> = Vec::new();
+    get_configurations(&mut configs, ConfigOptions::default());
+
+    configs.into_iter().map(|(config, front, rear, upper)| CompositeJob {
+        frame,
+        zmask: path(zmask, &config, "webp"),
+        sources: spec.used_sources().into_iter().map(|source| {
+            (source.to_string(), [&front, &rear, &upper].map(|x| path(&spec.sources[source], x, "exr")))
+        }).collect(),
+        outputs: spec.outputs.iter().map(|x| path(&x.dir, &config, x.format.extension())).collect(),
+        config,
+        upper,
+    }).collect()
+}
+
+
+/// Composites the outputs of `spec` for the configurations of `args.frame` that are out of date.
+pub fn composite(spec: &Spec, args: &CompositeArgs, common: &CommonArgs) {
+    let jobs = composite_jobs(spec, args.frame, &args.zmask, common);
+
+    write_stale_maps(spec, jobs, args.memory_budget, args.gpu_memory_budget, common, &mut BuildDb::new());
+}
+
+
+/// Writes the foreground light maps, and the colour passes if asked, that are out of date.
+pub fn foreground(args: &ForegroundArgs, common: &CommonArgs) {
+    let sources = BTreeMap::from([(foreground::SOURCE.to_string(), args.foreground.clone())]);
+    let spec = args.maps.spec(sources, foreground::outputs(&args.light, args.colour.as_deref()));
+
+    composite(&spec, &args.composite, common);
+}
+
+
+/// Writes the metal maps that are out of date.
+pub fn metal(args: &MetalArgs, common: &CommonArgs) {
+    let sources = BTreeMap::from([
+        (metal::SOURCES[0].to_string(), args.raw.clone()),
+        (metal::SOURCES[1].to_string(), args.polish.clone()),
+    ]);
+    let spec = args.maps.spec(sources, metal::outputs(&args.metal));
+
+    composite(&spec, &args.composite, common);
+}
@@ -0,0 +1,229 @@
+use clap::Args;
+use compositor::{ConfigOptions, Configuration, frame_path, get_configurations, pass_path};
+use exr::prelude::*;
+use rayon::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use depth::depth_channel;
+use matte::{CRYPTO_CHANNELS, MattePass, crypto_layer};
+use util::{CommonArgs, channel_list, matches_channel, thread_pool};
+use crate::project::{Project, level_path};
+use crate::run::parse_frames;
+
+
+/// Options of `pipeline verify`.
+#[derive(Args, Debug)]
+pub struct VerifyArgs {
+
+    /// JSON description of where every stage reads and writes
+    #[clap(long, parse(from_os_str))]
+    pub project: PathBuf,
+
+    /// Levels to check, e.g. `0,1,2`; the shared `--level` if omitted
+    #[clap(long, value_delimiter = ',')]
+    pub levels: Vec<u32>,
+
+    /// Frames counted from 0, e.g. `0-23` or `5`; all 144 if omitted
+    #[clap(long)]
+    pub frames: Option<String>,
+
+    /// Also decode every image to catch truncated pixel data, which is much slower
+    #[clap(long)]
+    pub decode: bool,
+}
+
+
+/// Channels a reader takes from an EXR.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Channels {
+    /// Depth, looked up by name.
+    Depth,
+    /// The ID and coverage channels of the first Cryptomatte layer.
+    Crypto,
+    /// R, G and B of each pass.
+    Passes(BTreeSet<String>),
+}
+
+/// An EXR some stage reads.
+pub struct Input {
+    /// What the input is, e.g. `depth F_STD`, for the report.
+    pub group: String,
+    pub level: u32,
+    pub frame: u32,
+    pub path: PathBuf,
+    pub channels: Channels,
+}
+
+
+/// Every EXR the stages of `project` read for `levels` and `frames`.
+pub fn inputs(project: &Project, base: u32, levels: &[u32], frames: &[u32]) -> Vec<Input> {
+    let spec = &project.spec;
+
+    let mut configs: Vec<Configuration> = Vec::new();
+    get_configurations(&mut configs, ConfigOptions::default());
+    let assemblies = configs.iter().flat_map(|(_, front, rear, upper)| [front, rear, upper]).collect::<BTreeSet<_>>();
+
+    let mut inputs = Vec::new();
+    for &level in levels {
+        for &frame in frames {
+            let mut add = |group: String, path: PathBuf, channels: Channels| inputs.push(Input { group, level, frame, path, channels });
+
+            add("plane".to_string(), level_path(&project.plane, base, level, frame, "exr"), Channels::Depth);
+            if let Some(mattes) = &project.mattes {
+                add("crypto".to_string(), level_path(&mattes.crypto, base, level, frame, "exr"), Channels::Crypto);
+            }
+            for assembly in &assemblies {
+                add(format!("depth {}", assembly), frame_path(&project.depth, base, assembly, level, frame, "exr"), Channels::Depth);
+                for source in spec.used_sources() {
+                    let path = frame_path(&spec.sources[source], base, assembly, level, frame, "exr");
+                    match spec.per_pass_files.contains(source) {
+                        true => for pass in spec.passes(source) {
+                            add(format!("{} {} {}", source, assembly, pass), pass_path(&path, &pass), Channels::Passes(BTreeSet::from([pass])));
+                        },
+                        false => add(format!("{} {}", source, assembly), path, Channels::Passes(spec.passes(source).into_iter().collect())),
+                    }
+                }
+            }
+        }
+    }
+    inputs
+}
+
+
+/// What is wrong with `input` at `resolution`, if anything.
+pub fn check(input: &Input, resolution: u32, decode: bool) -> Option<String> {
+    if !input.path.exists() {
+        return Some("missing".to_string());
+    }
+    let meta = match MetaData::read_from_file(&input.path, false) {
+        Ok(x) => x,
+        Err(e) => return Some(format!("unreadable header ({})", e)),
+    };
+
+    let header = &meta.headers[0];
+    let display = header.shared_attributes.display_window;
+    if (display.size.0, display.size.1) != (resolution as usize, resolution as usize) {
+        return Some(format!("{}x{}, expected {}x{}", display.size.0, display.size.1, resolution, resolution));
+    }
+
+    // Channels of every part, named `{layer}.{channel}` as the readers see them
+    let list = channel_list(&meta);
+    let find = |name: &str| list.iter().find(|x| matches_channel(&x.1, name));
+
+    // Channels read, and whether each holds Cryptomatte IDs
+    let required = match &input.channels {
+        Channels::Depth => match depth_channel(list.iter().map(|x| x.1.as_str())) {
+            Some(name) => vec![(find(&name).unwrap(), false)],
+            None => return Some("no depth channel".to_string()),
+        },
+        Channels::Crypto => {
+            let Some(layer) = crypto_layer(list.iter().map(|x| x.1.as_str())) else {
+                return Some("no Cryptomatte channels".to_string());
+            };
+            let mut required = Vec::new();
+            for (suffix, pass, _) in CRYPTO_CHANNELS {
+                match find(&format!("{}{}", layer, suffix)) {
+                    Some(x) => required.push((x, pass == MattePass::Index)),
+                    None => return Some(format!("no channel {}{}", layer, suffix)),
+                }
+            }
+            required
+        }
+        Channels::Passes(passes) => {
+            let mut required = Vec::new();
+            for pass in passes {
+                for name in ["R", "G", "B"] {
+                    match find(&format!("{}.{}", pass, name)) {
+                        Some(x) => required.push((x, false)),
+                        None => return Some(format!("no channel {}.{}", pass, name)),
+                    }
+                }
+            }
+            required
+        }
+    };
+    // Half floats can't hold ID hashes, and integers only make sense as IDs
+    for ((_, name, sample_type), id) in required {
+        match (sample_type, id) {
+            (SampleType::F16, true) => return Some(format!("{} is F16, IDs need F32 or U32", name)),
+            (SampleType::U32, false) => return Some(format!("{} is U32, expected F16 or F32", name)),
+            _ => {}
+        }
+    }
+
+    if decode {
+        let image = exr::prelude::read()
+            .no_deep_data()
+            .largest_resolution_level()
+            .all_channels()
+            .all_layers()
+            .all_attributes()
+            .from_file(&input.path);
+        if let Err(e) = image {
+            return Some(format!("corrupt ({})", e));
+        }
+    }
+
+    None
+}
+
+
+/// `0-23, 30` for frames 0 to 23 and 30.
+pub fn frame_ranges(frames: &[u32]) -> String {
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for &frame in frames {
+        match ranges.last_mut() {
+            Some((_, last)) if *last + 1 == frame => *last = frame,
+            _ => ranges.push((frame, frame)),
+        }
+    }
+    ranges.iter().map(|(first, last)| match first == last {
+        true => first.to_string(),
+        false => format!("{}-{}", first, last),
+    }).collect::<Vec<_>>().join(", ")
+}
+
+
+/// Checks `inputs` and prints a table of the missing and corrupt ones, one row per input, level
+/// and problem. Returns the number of bad inputs.
+pub fn report(inputs: &[Input], base: u32, decode: bool, common: &CommonArgs) -> usize {
+    let pool = thread_pool(common.jobs);
+    let problems: Vec<Option<String>> = pool.install(|| inputs.par_iter().map(|x| check(x, base * 2_u32.pow(x.level), decode)).collect());
+
+    let mut rows: BTreeMap<(&str, u32, &str), Vec<u32>> = BTreeMap::new();
+    for (input, problem) in inputs.iter().zip(&problems) {
+        if let Some(problem) = problem {
+            rows.entry((&input.group, input.level, problem)).or_default().push(input.frame);
+        }
+    }
+
+    let bad = problems.iter().flatten().count();
+    if bad == 0 {
+        println!("All {} inputs present and readable", inputs.len());
+        return 0;
+    }
+
+    println!("{:<32} {:>5}  {:<24} problem", "input", "level", "frames");
+    for ((group, level, problem), mut frames) in rows {
+        frames.sort();
+        println!("{:<32} {:>5}  {:<24} {}", group, level, frame_ranges(&frames), problem);
+    }
+    println!("{} of {} inputs missing or corrupt", bad, inputs.len());
+    bad
+}
+
+
+/// Checks every input of a project before any maps are computed.
+pub fn run(args: &VerifyArgs, common: &CommonArgs) {
+    let project = Project::from_file(&args.project);
+    let levels = match args.levels.is_empty() {
+        true => vec![common.level()],
+        false => args.levels.clone(),
+    };
+    let frames = parse_frames(&args.frames);
+
+    let inputs = inputs(&project, common.base_resolution(), &levels, &frames);
+    if report(&inputs, common.base_resolution(), args.decode, common) > 0 {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,105 @@
+use arrayfire::*;
+use compositor::*;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use util::LruCache;
+
+// Compares compositing the light map of every configuration by re-uploading its sub-assemblies
+// each time against keeping them resident on the device in an `LruCache`, as the compositor does.
+// The sub-assemblies are synthetic so that decoding EXRs isn't measured.
+//
+// Usage: composite_cache [resolution] [device] [gpu budget MiB]
+
+const PASSES: [&str; 3] = ["Diffuse", "Glossy", "AO"];
+
+/// Planar RGB of each light pass of a sub-assembly, different for every `seed`.
+fn passes(size: u64, seed: usize) -> PassesStruct {
+    let n = (size * size) as usize;
+    PassesStruct {
+        resolution: size as usize,
+        passes: PASSES.iter().enumerate().map(|(pass, name)| {
+            let values = (0..n * 3).map(|i| (((i * 31 + seed * 7 + pass) % 997) as f32 + 1.0) / 997.0).collect();
+            (name.to_string(), values)
+        }).collect(),
+    }
+}
+
+/// RGBA zmask with each pixel owned by the front, rear or upper at random.
+fn zmask(size: u64) -> Vec<u8> {
+    let n = (size * size) as usize;
+    (0..n).flat_map(|i| {
+        let owner = (i.wrapping_mul(2_654_435_761) >> 7) % 3;
+        [(owner == 0) as u8, (owner == 1) as u8, (owner == 2) as u8, 255]
+    }).collect()
+}
+
+/// Spec of the foreground compositor's light map, with the sources and output in `dir`.
+fn light(dir: PathBuf) -> Spec {
+    let channel = |pass: &str| Some(ChannelSpec { source: "foreground".to_string(), pass: pass.to_string(), component: Component::Luma });
+    Spec {
+        sources: BTreeMap::from([("foreground".to_string(), dir.clone())]),
+        working_space: WorkingSpace::default(),
+        luma_weights: None,
+        per_pass_files: BTreeSet::new(),
+        outputs: vec![Output {
+            name: "light".to_string(),
+            dir,
+            channels: PASSES.map(channel),
+            encoding: LogEncoding::default(),
+            format: OutputFormat::Webp,
+            lut: None,
+            alpha: false,
+        }],
+    }
+}
+
+fn report(name: &str, configs: usize, uploads: usize, elapsed: Duration) {
+    println!("{:<10} {:>8.2?} {:>8.2} configs/s {:>6} uploads", name, elapsed, configs as f64 / elapsed.as_secs_f64(), uploads);
+}
+
+fn main() {
+    let size = std::env::args().nth(1).map(|x| x.parse::<u64>().unwrap()).unwrap_or(512);
+    let device = std::env::args().nth(2).map(|x| x.parse::<i32>().unwrap()).unwrap_or(0);
+    let budget = std::env::args().nth(3).map(|x| x.parse::<usize>().unwrap() * 1024 * 1024);
+
+    info();
+    set_device(device);
+
+    let mut configs: Vec<Configuration> = Vec::new();
+    get_configurations(&mut configs, ConfigOptions::default());
+
+    let spec = light(PathBuf::new());
+    let channels = spec.channels("foreground");
+    let output = &spec.outputs[0];
+
+    let names = configs.iter().flat_map(|(_, front, rear, upper)| [front, rear, upper]).collect::<BTreeSet<_>>();
+    let host = names.iter().enumerate().map(|(i, name)| (name.to_string(), passes(size, i))).collect::<HashMap<_, _>>();
+    let masks = masks(&zmask(size), size);
+    let upload = |name: &str| upload(&host[name], &channels, spec.luma_weights(), size);
+
+    let composite_all = |load: &mut dyn FnMut(&str) -> Rc<LumaStruct>| {
+        for (_, front, rear, upper) in &configs {
+            let sources = HashMap::from([("foreground".to_string(), [front, rear, upper].map(|x| load(x)))]);
+            composite(output, &sources, &masks, size);
+        }
+        sync(device);
+    };
+
+    let start = Instant::now();
+    composite_all(&mut |name| Rc::new(upload(name)));
+    let reupload = start.elapsed();
+
+    let mut cache: LruCache<String, LumaStruct> = LruCache::new(budget);
+    let start = Instant::now();
+    composite_all(&mut |name| cache.get_or_load(name, || upload(name)));
+    let resident = start.elapsed();
+
+    println!("{} configurations of {} sub-assemblies at {}x{}", configs.len(), names.len(), size, size);
+    report("re-upload", configs.len(), 3 * configs.len(), reupload);
+    report("resident", configs.len(), cache.loads(), resident);
+    println!("speedup    {:.2}x", reupload.as_secs_f64() / resident.as_secs_f64());
+
+    mem_info!("After benchmark");
+}
@@ -0,0 +1,266 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use crate::is_stale;
+
+
+/// 64-bit FNV-1a, enough to tell whether a file changed between runs.
+pub(crate) struct Fnv(pub u64);
+
+impl Fnv {
+    pub fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = (self.0 ^ *b as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+
+/// Content hash of the file at `path`, or `None` if it can't be read.
+pub fn hash_file(path: &Path) -> Option<u64> {
+    let mut hasher = Fnv::new();
+    hasher.write(&fs::read(path).ok()?);
+    Some(hasher.0)
+}
+
+
+/// Content hashes of files, each hashed once per run. Can be shared between threads, and files
+/// are read and hashed without holding the lock.
+#[derive(Default)]
+pub struct FileHashes(Mutex<HashMap<PathBuf, Option<u64>>>);
+
+impl FileHashes {
+    /// Content hash of `path`, or `None` if it can't be read.
+    pub fn hash(&self, path: &Path) -> Option<u64> {
+        if let Some(hash) = self.0.lock().unwrap().get(path) {
+            return *hash;
+        }
+        let hash = hash_file(path);
+        self.0.lock().unwrap().insert(path.to_path_buf(), hash);
+        hash
+    }
+
+    /// Combined content hash of `inputs`, in order, as recorded in the build database, or `None`
+    /// if one can't be read.
+    pub fn fingerprint(&self, inputs: &[PathBuf]) -> Option<String> {
+        let mut hasher = Fnv::new();
+        for path in inputs {
+            hasher.write(&self.hash(path)?.to_le_bytes());
+        }
+        Some(format!("{:016x}", hasher.0))
+    }
+}
+
+
+/// What an output was built from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Record {
+    /// Combined content hash of the inputs, in order.
+    inputs: String,
+    /// Tool version and settings the output depends on.
+    params: String,
+}
+
+
+/// Remembers the inputs and settings every output was built from, so only outputs whose inputs
+/// or settings changed are rebuilt.
+///
+/// Records are kept next to the outputs, one `.build.json` per directory. Outputs without a
+/// record, e.g. built before the database existed, are compared by modification time instead
+/// and adopt the current inputs if they are fresh.
+#[derive(Default)]
+pub struct BuildDb {
+    shards: HashMap<PathBuf, (BTreeMap<String, Record>, bool)>,
+    hashes: FileHashes,
+}
+
+impl BuildDb {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn shard(&mut self, output: &Path) -> (&mut BTreeMap<String, Record>, &mut bool) {
+        let dir = output.parent().unwrap().to_path_buf();
+        let (records, dirty) = self.shards.entry(dir).or_insert_with_key(|dir| {
+            let records = fs::read_to_string(dir.join(".build.json")).ok().and_then(|x| serde_json::from_str(&x).ok());
+            (records.unwrap_or_default(), false)
+        });
+        (records, dirty)
+    }
+
+    fn key(output: &Path) -> String {
+        output.file_name().unwrap().to_string_lossy().to_string()
+    }
+
+    /// Content hash of `path`, or `None` if it can't be read. Each file is hashed once per run.
+    pub fn hash_file(&self, path: &Path) -> Option<u64> {
+        self.hashes.hash(path)
+    }
+
+    /// Whether `output` exists and was built from the current contents of `inputs` with `params`.
+    pub fn is_current(&mut self, output: &Path, inputs: &[PathBuf], params: &str) -> bool {
+        let fingerprint = self.hashes.fingerprint(inputs);
+        self.is_current_with_fingerprint(output, inputs, fingerprint.as_deref(), params)
+    }
+
+    /// Like `is_current` with the `fingerprint` of `inputs` computed beforehand, e.g. by
+    /// `FileHashes::fingerprint` outside a lock on the database.
+    pub fn is_current_with_fingerprint(&mut self, output: &Path, inputs: &[PathBuf], fingerprint: Option<&str>, params: &str) -> bool {
+        if !output.exists() {
+            return false;
+        }
+        let Some(fingerprint) = fingerprint else {
+            return false;
+        };
+
+        let key = Self::key(output);
+        let (records, dirty) = self.shard(output);
+        match records.get(&key) {
+            Some(record) => record.inputs == fingerprint && record.params == params,
+            None if !is_stale(&[output.to_path_buf()], inputs) => {
+                records.insert(key, Record { inputs: fingerprint.to_string(), params: params.to_string() });
+                *dirty = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Notes that `output` was (re)built from `inputs` with `params`.
+    pub fn record(&mut self, output: &Path, inputs: &[PathBuf], params: &str) {
+        let fingerprint = self.hashes.fingerprint(inputs);
+        self.record_fingerprint(output, fingerprint.as_deref(), params);
+    }
+
+    /// Like `record` with the `fingerprint` of the inputs computed beforehand.
+    pub fn record_fingerprint(&mut self, output: &Path, fingerprint: Option<&str>, params: &str) {
+        let Some(fingerprint) = fingerprint else {
+            return;
+        };
+
+        let key = Self::key(output);
+        let (records, dirty) = self.shard(output);
+        records.insert(key, Record { inputs: fingerprint.to_string(), params: params.to_string() });
+        *dirty = true;
+    }
+
+    /// Writes the records that changed. Call once the outputs have been written.
+    pub fn save(&mut self) {
+        for (dir, (records, dirty)) in self.shards.iter_mut().filter(|(_, (_, dirty))| *dirty) {
+            let _ = fs::create_dir_all(dir);
+            fs::write(dir.join(".build.json"), serde_json::to_string(records).unwrap()).unwrap();
+            *dirty = false;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use golden::scratch_dir;
+    use std::time::{Duration, SystemTime};
+
+    /// Writes `contents` to `dir/name` and returns its path.
+    fn write(dir: &Path, name: &str, contents: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn changed_inputs_make_outputs_stale() {
+        let dir = scratch_dir("builddb_inputs");
+        let inputs = vec![write(&dir, "a.exr", "a"), write(&dir, "b.exr", "b")];
+        let output = write(&dir, "out/0121.webp", "");
+
+        let mut db = BuildDb::new();
+        db.record(&output, &inputs, "v1");
+        assert!(db.is_current(&output, &inputs, "v1"));
+
+        // Hashes are cached for a run, so the change shows in the next one
+        write(&dir, "b.exr", "changed");
+        assert!(!BuildDb::new().is_current(&output, &inputs, "v1"));
+    }
+
+    #[test]
+    fn fingerprints_from_file_hashes_match_the_database() {
+        let dir = scratch_dir("builddb_fingerprint");
+        let inputs = vec![write(&dir, "a.exr", "a"), write(&dir, "b.exr", "b")];
+        let output = write(&dir, "out/0121.webp", "");
+        let hashes = FileHashes::default();
+
+        let mut db = BuildDb::new();
+        db.record_fingerprint(&output, hashes.fingerprint(&inputs).as_deref(), "v1");
+        assert!(db.is_current(&output, &inputs, "v1"));
+        assert!(!db.is_current_with_fingerprint(&output, &inputs, hashes.fingerprint(&inputs[..1]).as_deref(), "v1"));
+
+        // Unreadable inputs are never current
+        let missing = vec![dir.join("missing.exr")];
+        assert_eq!(hashes.fingerprint(&missing), None);
+        assert!(!db.is_current_with_fingerprint(&output, &missing, None, "v1"));
+    }
+
+    #[test]
+    fn changed_params_make_outputs_stale() {
+        let dir = scratch_dir("builddb_params");
+        let inputs = vec![write(&dir, "a.exr", "a")];
+        let output = write(&dir, "out/0121.webp", "");
+
+        let mut db = BuildDb::new();
+        db.record(&output, &inputs, "v1");
+        db.save();
+
+        let mut db = BuildDb::new();
+        assert!(db.is_current(&output, &inputs, "v1"));
+        assert!(!db.is_current(&output, &inputs, "v2"));
+    }
+
+    #[test]
+    fn fresh_outputs_without_a_record_are_adopted() {
+        let dir = scratch_dir("builddb_adopt");
+        let inputs = vec![write(&dir, "a.exr", "a")];
+        let fresh = write(&dir, "out/0121.webp", "");
+        let old = write(&dir, "out/0122.webp", "");
+        let modified = SystemTime::now() - Duration::from_secs(3600);
+        fs::File::options().write(true).open(&old).unwrap().set_modified(modified).unwrap();
+
+        let mut db = BuildDb::new();
+        assert!(db.is_current(&fresh, &inputs, "v1"));
+        assert!(!db.is_current(&old, &inputs, "v1"));
+        db.save();
+
+        // Adopted with the inputs and params it was checked against
+        let mut db = BuildDb::new();
+        assert!(!db.is_current(&fresh, &inputs, "v2"));
+        write(&dir, "a.exr", "changed");
+        assert!(!BuildDb::new().is_current(&fresh, &inputs, "v1"));
+    }
+
+    #[test]
+    fn records_are_sharded_per_directory() {
+        let dir = scratch_dir("builddb_shards");
+        let inputs = vec![write(&dir, "a.exr", "a")];
+        let outputs = [write(&dir, "light/0121.webp", ""), write(&dir, "metal/0121.webp", "")];
+
+        let mut db = BuildDb::new();
+        for output in &outputs {
+            db.record(output, &inputs, "v1");
+        }
+        db.save();
+
+        for output in &outputs {
+            let shard = output.parent().unwrap().join(".build.json");
+            let records: BTreeMap<String, Record> = serde_json::from_str(&fs::read_to_string(shard).unwrap()).unwrap();
+            assert_eq!(records.keys().collect::<Vec<_>>(), ["0121.webp"]);
+        }
+        assert!(!dir.join(".build.json").exists());
+    }
+}
@@ -0,0 +1,161 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::rc::Rc;
+
+
+/// Approximate number of bytes a cached value keeps alive.
+pub trait MemorySize {
+    fn memory_size(&self) -> usize;
+}
+
+
+/// Least-recently-used cache bounded by the total `MemorySize` of its values.
+///
+/// Values are handed out as `Rc`, so an entry evicted while still in use stays alive until its
+/// last user drops it. A budget of `None` never evicts.
+pub struct LruCache<K, V> {
+    budget: Option<usize>,
+    used: usize,
+    tick: u64,
+    loads: usize,
+    hits: usize,
+    entries: HashMap<K, (Rc<V>, u64)>,
+}
+
+impl<K: Eq + Hash + Clone, V: MemorySize> LruCache<K, V> {
+    pub fn new(budget: Option<usize>) -> Self {
+        Self {
+            budget,
+            used: 0,
+            tick: 0,
+            loads: 0,
+            hits: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn get_or_load<Q, F>(&mut self, key: &Q, load: F) -> Rc<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ToOwned<Owned = K> + ?Sized,
+        F: FnOnce() -> V,
+    {
+        self.tick += 1;
+        if let Some(entry) = self.entries.get_mut(key) {
+            entry.1 = self.tick;
+            self.hits += 1;
+            return entry.0.clone();
+        }
+
+        self.insert(key.to_owned(), load())
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.entries.contains_key(key)
+    }
+
+    /// Adds a value loaded elsewhere, e.g. decoded ahead of time on another thread.
+    pub fn insert(&mut self, key: K, value: V) -> Rc<V> {
+        self.tick += 1;
+        if let Some((old, _)) = self.entries.remove(&key) {
+            self.used -= old.memory_size();
+        }
+
+        let value = Rc::new(value);
+        self.loads += 1;
+        self.make_room(value.memory_size());
+        self.used += value.memory_size();
+        self.entries.insert(key, (value.clone(), self.tick));
+        value
+    }
+
+    /// Number of values that had to be loaded.
+    pub fn loads(&self) -> usize {
+        self.loads
+    }
+
+    /// Number of lookups served from the cache.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    fn make_room(&mut self, size: usize) {
+        let budget = match self.budget {
+            Some(budget) => budget,
+            None => return,
+        };
+
+        while self.used + size > budget {
+            let oldest = self.entries.iter().min_by_key(|(_, (_, tick))| *tick).map(|(k, _)| k.clone());
+            match oldest {
+                Some(key) => {
+                    let (value, _) = self.entries.remove(&key).unwrap();
+                    self.used -= value.memory_size();
+                },
+                None => break,
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Bytes(usize);
+
+    impl MemorySize for Bytes {
+        fn memory_size(&self) -> usize {
+            self.0
+        }
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = LruCache::new(Some(30));
+        cache.get_or_load("a", || Bytes(10));
+        cache.get_or_load("b", || Bytes(10));
+        cache.get_or_load("c", || Bytes(10));
+
+        // Using `a` makes `b` the oldest
+        let a = cache.get_or_load("a", || unreachable!());
+        cache.get_or_load("d", || Bytes(10));
+        assert!(cache.contains("a") && !cache.contains("b") && cache.contains("c") && cache.contains("d"));
+        assert_eq!(cache.used, 30);
+
+        // A large value evicts as much as it needs, values in use stay alive
+        cache.get_or_load("e", || Bytes(25));
+        assert!(!cache.contains("a") && !cache.contains("c") && !cache.contains("d"));
+        assert_eq!(cache.used, 25);
+        assert_eq!(a.0, 10);
+
+        assert_eq!((cache.loads(), cache.hits()), (5, 1));
+    }
+
+    #[test]
+    fn replacing_a_value_updates_the_budget() {
+        let mut cache = LruCache::new(Some(30));
+        cache.insert("a", Bytes(10));
+        cache.insert("a", Bytes(20));
+        assert_eq!(cache.used, 20);
+
+        cache.insert("b", Bytes(10));
+        assert!(cache.contains("a") && cache.contains("b"));
+    }
+
+    #[test]
+    fn unbounded_cache_never_evicts() {
+        let mut cache = LruCache::new(None);
+        for i in 0..100 {
+            cache.get_or_load(&i, || Bytes(1 << 20));
+        }
+        assert!((0..100).all(|i| cache.contains(&i)));
+        assert_eq!(cache.used, 100 << 20);
+    }
+}
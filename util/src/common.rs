@@ -0,0 +1,132 @@
+use clap::Args;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::{ComputeBackend, exr_resolution};
+
+
+/// Options shared by every stage of the pipeline.
+///
+/// Each can also come from a JSON config file with the same keys, e.g.
+/// `{ "base_resolution": 256, "level": 2, "devices": [0, 1], "backend": "cuda" }`; options given on
+/// the command line take precedence.
+#[derive(Args, Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CommonArgs {
+
+    #[clap(long, global = true)]
+    pub base_resolution: Option<u32>,
+
+    /// Resolution level, the maps are `base_resolution * 2^level` pixels square
+    #[clap(long, global = true)]
+    pub level: Option<u32>,
+
+    /// Devices to spread the work across, e.g. `0,1,2,3`; device 0 if omitted
+    #[clap(long, alias = "device", value_delimiter = ',', global = true)]
+    pub devices: Vec<i32>,
+
+    /// ArrayFire backend to compute on; CUDA if omitted
+    #[clap(long, arg_enum, global = true)]
+    pub backend: Option<ComputeBackend>,
+
+    #[clap(long, global = true, overrides_with = "no_overwrite")]
+    pub overwrite: bool,
+
+    /// Skip the outputs that are up to date even if the config file sets `overwrite`
+    #[clap(long, global = true, overrides_with = "overwrite")]
+    #[serde(skip)]
+    pub no_overwrite: bool,
+
+    /// Number of threads decoding EXRs and encoding images; one per core if omitted
+    #[clap(long, global = true)]
+    pub jobs: Option<usize>,
+
+    /// JSON manifest to list the written maps in, merged with what earlier runs listed
+    #[clap(long, parse(from_os_str), global = true)]
+    pub manifest: Option<PathBuf>,
+}
+
+impl CommonArgs {
+    pub fn from_file(path: &Path) -> Self {
+        let text = fs::read_to_string(path).unwrap_or_else(|e| panic!("Error: cannot read config {:?} ({})", path, e));
+        serde_json::from_str(&text).unwrap_or_else(|e| panic!("Error: invalid config {:?} ({})", path, e))
+    }
+
+    /// Fills the options missing from these with those of `defaults`.
+    pub fn or(self, defaults: CommonArgs) -> Self {
+        Self {
+            base_resolution: self.base_resolution.or(defaults.base_resolution),
+            level: self.level.or(defaults.level),
+            devices: if self.devices.is_empty() { defaults.devices } else { self.devices },
+            backend: self.backend.or(defaults.backend),
+            overwrite: self.overwrite || (!self.no_overwrite && defaults.overwrite),
+            no_overwrite: self.no_overwrite,
+            jobs: self.jobs.or(defaults.jobs),
+            manifest: self.manifest.or(defaults.manifest),
+        }
+    }
+
+    pub fn base_resolution(&self) -> u32 {
+        self.base_resolution.unwrap_or_else(|| panic!("Error: --base-resolution is required"))
+    }
+
+    pub fn level(&self) -> u32 {
+        self.level.unwrap_or_else(|| panic!("Error: --level is required"))
+    }
+
+    pub fn resolution(&self) -> u32 {
+        self.base_resolution() * 2_u32.pow(self.level())
+    }
+
+    /// `resolution` if given, else `base_resolution * 2^level`, else that of the render at `path`.
+    pub fn resolution_or_infer(&self, resolution: Option<u32>, path: &Path) -> u32 {
+        if let Some(x) = resolution.or(self.base_resolution.map(|_| self.resolution())) {
+            return x;
+        }
+        let x = exr_resolution(path);
+        println!("Resolution {} read from {:?}", x, path);
+        x
+    }
+
+    pub fn devices(&self) -> Vec<i32> {
+        if self.devices.is_empty() { vec![0] } else { self.devices.clone() }
+    }
+
+    pub fn backend(&self) -> ComputeBackend {
+        self.backend.unwrap_or(ComputeBackend::Cuda)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[clap(flatten)]
+        common: CommonArgs,
+    }
+
+    fn parse(args: &[&str]) -> CommonArgs {
+        Cli::parse_from([&["pipeline"], args].concat()).common
+    }
+
+    #[test]
+    fn command_line_overrides_config_overwrite() {
+        let config: CommonArgs = serde_json::from_str(r#"{ "overwrite": true, "level": 2 }"#).unwrap();
+
+        assert!(parse(&[]).or(config.clone()).overwrite);
+        assert!(!parse(&["--no-overwrite"]).or(config.clone()).overwrite);
+        assert!(parse(&["--no-overwrite", "--overwrite"]).or(config.clone()).overwrite);
+        assert!(parse(&["--overwrite"]).or(CommonArgs::default()).overwrite);
+        assert_eq!(parse(&["--level", "3"]).or(config).level, Some(3));
+    }
+
+    #[test]
+    #[should_panic(expected = "unknown field")]
+    fn config_cannot_set_no_overwrite() {
+        let _: CommonArgs = serde_json::from_str(r#"{ "no_overwrite": true }"#).unwrap();
+    }
+}
@@ -0,0 +1,84 @@
+use arrayfire::{Backend, set_backend, set_device};
+use clap::ArgEnum;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+
+/// ArrayFire backend to compute on. The CPU backend needs no GPU, e.g. for the tests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ComputeBackend {
+    Cuda,
+    Opencl,
+    Cpu,
+}
+
+/// Selects `backend` and `device` for the calling thread.
+pub fn use_device(backend: ComputeBackend, device: i32) {
+    set_backend(match backend {
+        ComputeBackend::Cuda => Backend::CUDA,
+        ComputeBackend::Opencl => Backend::OPENCL,
+        ComputeBackend::Cpu => Backend::CPU,
+    });
+    set_device(device);
+}
+
+
+/// What one device did during `run_on_devices`.
+pub struct DeviceSummary {
+    pub device: i32,
+    pub tasks: usize,
+    pub busy: Duration,
+}
+
+
+/// Runs `tasks` from one thread per device.
+///
+/// Each thread sets up its own state with `init` (select the device there, state may hold device
+/// arrays) and then keeps claiming the next unprocessed task, so faster devices take on more of
+/// the work. `work` returns a short description of the finished task for the progress log.
+pub fn run_on_devices<T, S, I, F>(devices: &[i32], tasks: &[T], init: I, work: F) -> Vec<DeviceSummary>
+where
+    T: Sync,
+    I: Fn(i32) -> S + Sync,
+    F: Fn(&mut S, &T) -> String + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let (init, work, next, done) = (&init, &work, &next, &done);
+
+    std::thread::scope(|scope| {
+        let handles = devices.iter().map(|&device| scope.spawn(move || {
+            let mut state = init(device);
+            let mut summary = DeviceSummary { device, tasks: 0, busy: Duration::ZERO };
+
+            loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                if i >= tasks.len() {
+                    break;
+                }
+
+                let start = Instant::now();
+                let description = work(&mut state, &tasks[i]);
+                summary.busy += start.elapsed();
+                summary.tasks += 1;
+
+                let n = done.fetch_add(1, Ordering::SeqCst) + 1;
+                println!("[{}/{}] device {}: {}", n, tasks.len(), device, description);
+            }
+
+            summary
+        })).collect::<Vec<_>>();
+
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    })
+}
+
+
+pub fn print_summary(summaries: &[DeviceSummary], elapsed: Duration) {
+    for s in summaries {
+        println!("device {}: {} tasks, busy {:.1?}", s.device, s.tasks, s.busy);
+    }
+    println!("{} tasks on {} devices in {:.1?}", summaries.iter().map(|s| s.tasks).sum::<usize>(), summaries.len(), elapsed);
+}
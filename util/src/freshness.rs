@@ -0,0 +1,22 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|x| x.modified()).ok()
+}
+
+
+/// Whether `outputs` need rebuilding, make-style: one of them is missing or older than the newest
+/// of `inputs`. A missing input also counts so the stage gets to report it.
+pub fn is_stale(outputs: &[PathBuf], inputs: &[PathBuf]) -> bool {
+    let oldest_output = outputs.iter().map(modified).collect::<Option<Vec<_>>>().and_then(|x| x.into_iter().min());
+    let newest_input = inputs.iter().map(modified).collect::<Option<Vec<_>>>().map(|x| x.into_iter().max());
+
+    match (oldest_output, newest_input) {
+        (Some(output), Some(Some(input))) => output < input,
+        (Some(_), Some(None)) => false,
+        _ => true,
+    }
+}
@@ -0,0 +1,69 @@
+use std::path::PathBuf;
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use crate::{WebpCompressionType, save_webp};
+
+
+/// Thread pool used to decode EXRs. `None` uses one thread per core.
+pub fn thread_pool(jobs: Option<usize>) -> rayon::ThreadPool {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.unwrap_or(0))
+        .build()
+        .unwrap()
+}
+
+
+type WriteJob = Box<dyn FnOnce() + Send>;
+
+
+/// Encodes and writes images on background threads so compositing doesn't wait on them.
+///
+/// At most `jobs` images wait in the queue; submitting blocks once it is full. Dropping the writer
+/// waits for every queued image to be written.
+pub struct ImageWriter {
+    sender: Option<SyncSender<WriteJob>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ImageWriter {
+    pub fn new(jobs: usize) -> Self {
+        let (sender, receiver) = sync_channel::<WriteJob>(jobs);
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..jobs.max(1)).map(|_| {
+            let receiver = receiver.clone();
+            std::thread::spawn(move || loop {
+                let job = receiver.lock().unwrap().recv();
+                match job {
+                    Ok(job) => job(),
+                    Err(_) => break,
+                }
+            })
+        }).collect();
+
+        Self { sender: Some(sender), workers }
+    }
+
+    pub fn submit(&self, path: PathBuf, size: u32, pixels: Vec<u8>, compression: WebpCompressionType) {
+        self.submit_with(move || save_webp(path, size, &pixels, compression));
+    }
+
+    /// Queues any other write, e.g. a format other than WebP.
+    pub fn submit_with<F: FnOnce() + Send + 'static>(&self, job: F) {
+        self.sender.as_ref().unwrap().send(Box::new(job)).expect("Image writer thread panicked");
+    }
+}
+
+impl Drop for ImageWriter {
+    fn drop(&mut self) {
+        self.sender = None;
+        for worker in self.workers.drain(..) {
+            if let Err(e) = worker.join() {
+                if !std::thread::panicking() {
+                    std::panic::resume_unwind(e);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use exr::block::UncompressedBlock;
+use exr::block::chunk::TileCoordinates;
+use exr::image::read::any_channels::{ReadSamples, SamplesReader};
+use exr::image::read::layers::{ChannelsReader, ReadChannels};
+use exr::image::read::samples::{FlatSamplesReader, ReadFlatSamples};
+use exr::meta::header::Header;
+use exr::prelude::*;
+use std::path::{Path, PathBuf};
+use crate::{check_display_window, float_samples, id_samples, place_in_display_window};
+
+
+/// Whether the channel `name` is `channel`, possibly prefixed with layers: `Diffuse.R` matches
+/// `ViewLayer.Diffuse.R`, and `ViewLayer.Diffuse.R` selects that view layer.
+pub fn matches_channel(name: &str, channel: &str) -> bool {
+    name == channel || name.strip_suffix(channel).is_some_and(|x| x.ends_with('.'))
+}
+
+
+/// `{layer}.{channel}`, or just `channel` in a layer or part without a name.
+fn channel_name(layer: Option<&Text>, channel: &Text) -> String {
+    match layer.filter(|x| !x.bytes().is_empty()) {
+        Some(layer) => format!("{}.{}", layer, channel),
+        None => channel.to_string(),
+    }
+}
+
+/// Layer or part index, name and sample type of every channel in the file, named as in
+/// `ExrChannels`, to pick the channels to read from the headers alone.
+pub fn channel_list(meta: &MetaData) -> Vec<(usize, String, SampleType)> {
+    meta.headers.iter().enumerate().flat_map(|(i, header)| {
+        header.channels.list.iter().map(move |x| (i, channel_name(header.own_attributes.layer_name.as_ref(), &x.name), x.sample_type))
+    }).collect()
+}
+
+
+/// Reads the channels matching one of `wanted`. Parts holding none of them are skipped before
+/// decompression and the other channels of a part are never stored.
+struct ReadWantedChannels<'a> {
+    wanted: &'a [String],
+}
+
+/// Samples of the wanted channels of one layer or part, `None` for the skipped ones.
+struct WantedChannelsReader {
+    channels: Vec<Option<(ChannelDescription, FlatSamplesReader)>>,
+}
+
+impl<'s> ReadChannels<'s> for ReadWantedChannels<'_> {
+    type Reader = WantedChannelsReader;
+
+    fn create_channels_reader(&'s self, header: &Header) -> exr::error::Result<Self::Reader> {
+        let mut channels = Vec::new();
+        for channel in &header.channels.list {
+            let name = channel_name(header.own_attributes.layer_name.as_ref(), &channel.name);
+            channels.push(match self.wanted.iter().any(|x| matches_channel(&name, x)) {
+                true => Some((channel.clone(), ReadFlatSamples.create_sample_reader(header, channel)?)),
+                false => None,
+            });
+        }
+        Ok(WantedChannelsReader { channels })
+    }
+}
+
+impl ChannelsReader for WantedChannelsReader {
+    type Channels = AnyChannels<FlatSamples>;
+
+    fn filter_block(&self, tile: TileCoordinates) -> bool {
+        self.channels.iter().flatten().any(|(_, samples)| samples.filter_block(tile))
+    }
+
+    fn read_block(&mut self, header: &Header, block: UncompressedBlock) -> exr::error::UnitResult {
+        for line in block.lines(&header.channels) {
+            if let Some((_, samples)) = &mut self.channels[line.location.channel] {
+                samples.read_line(line)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn into_channels(self) -> Self::Channels {
+        AnyChannels {
+            list: self.channels.into_iter().flatten().map(|(channel, samples)| AnyChannel {
+                name: channel.name,
+                sample_data: samples.into_samples(),
+                quantize_linearly: channel.quantize_linearly,
+                sampling: channel.sampling,
+            }).collect(),
+        }
+    }
+}
+
+
+/// One channel of an EXR, named `{layer}.{channel}` if its layer or part has a name.
+pub struct ExrChannel {
+    pub name: String,
+    /// Index of the layer or part in the file.
+    pub layer: usize,
+    /// Data window of the layer.
+    pub data: IntegerBounds,
+    pub samples: FlatSamples,
+}
+
+/// The wanted channels of every layer and part of an EXR, so passes Blender writes to separate
+/// parts or view layers are all found.
+pub struct ExrChannels {
+    pub path: PathBuf,
+    pub attributes: ImageAttributes,
+    pub channels: Vec<ExrChannel>,
+}
+
+impl ExrChannels {
+    /// Reads the channels of `path` matching one of `channels`, see `matches_channel`. Its
+    /// display window must be `resolution` square.
+    pub fn read(path: &Path, resolution: u32, channels: &[String]) -> Self {
+        // Like `read().no_deep_data().largest_resolution_level()`, but with only the wanted channels
+        let image = ReadWantedChannels { wanted: channels }
+            .all_layers()
+            .all_attributes()
+            .from_file(path)
+            .unwrap_or_else(|e| panic!("Error: cannot read {:?} ({})", path, e));
+        check_display_window(path, image.attributes.display_window, resolution);
+
+        let mut channels = Vec::new();
+        for (i, layer) in image.layer_data.into_iter().enumerate() {
+            let data = layer.absolute_bounds();
+            for channel in layer.channel_data.list {
+                channels.push(ExrChannel {
+                    name: channel_name(layer.attributes.layer_name.as_ref(), &channel.name),
+                    layer: i,
+                    data,
+                    samples: channel.sample_data,
+                });
+            }
+        }
+
+        Self {
+            path: path.to_path_buf(),
+            attributes: image.attributes,
+            channels,
+        }
+    }
+
+    /// The first channel matching `channel`, see `matches_channel`.
+    pub fn find(&self, channel: &str) -> Option<&ExrChannel> {
+        self.channels.iter().find(|x| matches_channel(&x.name, channel))
+    }
+
+    /// Colour, depth or coverage samples of `channel` over the display window, `fill` where it
+    /// wasn't rendered.
+    pub fn floats(&self, channel: &ExrChannel, fill: f32) -> Vec<f32> {
+        let samples = float_samples(&self.path, &channel.name, &channel.samples);
+        place_in_display_window(&samples, self.attributes.display_window, channel.data, fill)
+    }
+
+    /// Cryptomatte ID bits of `channel` over the display window, 0.0 where it wasn't rendered.
+    pub fn ids(&self, channel: &ExrChannel) -> Vec<u32> {
+        let samples = id_samples(&self.path, &channel.name, &channel.samples);
+        place_in_display_window(&samples, self.attributes.display_window, channel.data, 0)
+    }
+}
@@ -5,6 +5,27 @@ use std::path::{Path, PathBuf};
 use exr::prelude::*;
 use webp;
 
+mod builddb;
+mod cache;
+mod common;
+mod devices;
+mod freshness;
+mod jobs;
+mod layers;
+mod manifest;
+mod samples;
+mod windows;
+pub use builddb::{BuildDb, FileHashes, hash_file};
+pub use cache::{LruCache, MemorySize};
+pub use common::CommonArgs;
+pub use devices::{ComputeBackend, DeviceSummary, print_summary, run_on_devices, use_device};
+pub use freshness::is_stale;
+pub use jobs::{ImageWriter, thread_pool};
+pub use layers::{ExrChannel, ExrChannels, channel_list, matches_channel};
+pub use manifest::{FileEntry, Manifest, ManifestEntry, Product};
+pub use samples::{float_samples, id_samples};
+pub use windows::{check_display_window, exr_resolution, place_in_display_window};
+
 #[derive(Debug)]
 pub enum RGBAChannel {
     R,
@@ -18,12 +39,63 @@ pub enum WebpCompressionType {
     LOSSLESS,
 }
 
+/// Whether interleaved `pixels` of a `size` square image are RGBA rather than RGB.
+fn has_alpha<T>(size: u32, pixels: &[T]) -> bool {
+    pixels.len() == 4 * size as usize * size as usize
+}
+
+/// Saves interleaved RGB, or RGBA if `pixels` holds four values per pixel.
 pub fn save_webp(path: PathBuf, size: u32, pixels: &Vec<u8>, compression: WebpCompressionType) {
+    let encoder = match has_alpha(size, pixels) {
+        true => webp::Encoder::from_rgba(pixels, size, size),
+        false => webp::Encoder::from_rgb(pixels, size, size),
+    };
     let img = match compression {
-        WebpCompressionType::LOSSLESS => webp::Encoder::from_rgb(pixels, size, size).encode_lossless(),
-        WebpCompressionType::LOSSY(quality) => webp::Encoder::from_rgb(pixels, size, size).encode(quality),
+        WebpCompressionType::LOSSLESS => encoder.encode_lossless(),
+        WebpCompressionType::LOSSY(quality) => encoder.encode(quality),
     };
     let _ = fs::create_dir_all(path.clone().parent().unwrap());
     let mut buffered_file_write = BufWriter::new(fs::File::create(path).unwrap());
     buffered_file_write.write_all(&img).unwrap();
 }
+
+
+/// Saves interleaved RGB, or RGBA if `pixels` holds four values per pixel.
+pub fn save_png16(path: PathBuf, size: u32, pixels: &[u16]) {
+    let _ = fs::create_dir_all(path.parent().unwrap());
+    match has_alpha(size, pixels) {
+        true => image::ImageBuffer::<image::Rgba<u16>, _>::from_raw(size, size, pixels.to_vec()).unwrap().save(path).unwrap(),
+        false => image::ImageBuffer::<image::Rgb<u16>, _>::from_raw(size, size, pixels.to_vec()).unwrap().save(path).unwrap(),
+    }
+}
+
+/// Saves interleaved RGB, or RGBA if `pixels` holds four values per pixel.
+pub fn save_exr_half(path: PathBuf, size: u32, pixels: &[f32]) {
+    let alpha = has_alpha(size, pixels);
+    let size = size as usize;
+    let _ = fs::create_dir_all(path.parent().unwrap());
+    match alpha {
+        true => write_rgba_file(path, size, size, |x, y| {
+            let i = (y * size + x) * 4;
+            (f16::from_f32(pixels[i]), f16::from_f32(pixels[i + 1]), f16::from_f32(pixels[i + 2]), f16::from_f32(pixels[i + 3]))
+        }).unwrap(),
+        false => write_rgb_file(path, size, size, |x, y| {
+            let i = (y * size + x) * 3;
+            (f16::from_f32(pixels[i]), f16::from_f32(pixels[i + 1]), f16::from_f32(pixels[i + 2]))
+        }).unwrap(),
+    }
+}
+
+
+/// Files of `dir`, which holds one render per frame, sorted so that the index is the frame.
+pub fn frame_files(dir: &Path, frames: usize) -> Vec<PathBuf> {
+    let mut files = fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("Error: cannot read {:?} ({})", dir, e))
+        .map(|x| x.unwrap().path())
+        .collect::<Vec<_>>();
+    if files.len() != frames {
+        panic!("Error: {:?} holds {} files, expected one per frame ({})", dir, files.len(), frames);
+    }
+    files.sort();
+    files
+}
@@ -0,0 +1,102 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use crate::hash_file;
+
+
+/// Everything the web configurator can load, merged across runs.
+///
+/// ```json
+/// {
+///   "products": {
+///     "light": {
+///       "encoding": { "format": "webp", "encoding": { "middle_grey": 0.18, ... }, ... },
+///       "configurations": {
+///         "20_L_STD_...": { "1024": { "0121": { "bytes": 81234, "hash": "9f3c..." } } }
+///       }
+///     }
+///   },
+///   "luts": { "filmic_desat65cube.bin": { "bytes": 1647750, "hash": "..." } }
+/// }
+/// ```
+///
+/// Products that don't vary by configuration list their frames under the configuration `""`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(default)]
+    pub products: BTreeMap<String, Product>,
+    #[serde(default)]
+    pub luts: BTreeMap<String, FileEntry>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Product {
+    /// How to decode the maps: log scale, packing scheme and so on.
+    pub encoding: Value,
+    /// Frames by configuration, then resolution, then frame number.
+    pub configurations: BTreeMap<String, BTreeMap<u32, BTreeMap<String, FileEntry>>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub bytes: u64,
+    pub hash: String,
+}
+
+impl FileEntry {
+    pub fn new(path: &Path) -> Option<Self> {
+        Some(Self {
+            bytes: fs::metadata(path).ok()?.len(),
+            hash: format!("{:016x}", hash_file(path)?),
+        })
+    }
+}
+
+
+/// One written map to list in the manifest.
+pub struct ManifestEntry<'a> {
+    pub product: &'a str,
+    pub config: &'a str,
+    pub resolution: u32,
+    /// Frame index from 0, listed as its file number.
+    pub frame: u32,
+    pub path: &'a Path,
+}
+
+
+impl Manifest {
+    /// Reads the manifest at `path`, applies `f` and writes it back, so runs only add to it.
+    pub fn update<F: FnOnce(&mut Manifest)>(path: &Path, f: F) {
+        let mut manifest: Manifest = match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|e| panic!("Error: invalid manifest {:?} ({})", path, e)),
+            Err(_) => Manifest::default(),
+        };
+
+        f(&mut manifest);
+
+        // Write next to it and rename so readers never see a partial manifest
+        let tmp = path.with_extension("json.tmp");
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        fs::write(&tmp, serde_json::to_string_pretty(&manifest).unwrap()).unwrap();
+        fs::rename(&tmp, path).unwrap();
+    }
+
+    /// Lists `entries`, replacing the product's encoding with `encoding`.
+    pub fn add(&mut self, encoding: &Value, entries: &[ManifestEntry]) {
+        for entry in entries {
+            let Some(file) = FileEntry::new(entry.path) else {
+                continue;
+            };
+            let product = self.products.entry(entry.product.to_string()).or_default();
+            product.encoding = encoding.clone();
+            product.configurations
+                .entry(entry.config.to_string()).or_default()
+                .entry(entry.resolution).or_default()
+                .insert(format!("{:0>4}", 121 + entry.frame), file);
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use exr::prelude::FlatSamples;
+use std::path::Path;
+
+
+/// Samples of a colour, depth or coverage channel as f32, widening half floats.
+pub fn float_samples(path: &Path, name: &str, samples: &FlatSamples) -> Vec<f32> {
+    match samples {
+        FlatSamples::F16(x) => x.iter().map(|x| x.to_f32()).collect(),
+        FlatSamples::F32(x) => x.to_owned(),
+        FlatSamples::U32(_) => panic!("Error: {:?} channel {} holds integers, expected half or full floats", path, name),
+    }
+}
+
+
+/// Bits of a Cryptomatte ID channel, stored as full floats or as integers. Half floats can't hold
+/// the 32-bit hashes, so they are rejected.
+pub fn id_samples(path: &Path, name: &str, samples: &FlatSamples) -> Vec<u32> {
+    match samples {
+        FlatSamples::F32(x) => x.iter().map(|x| x.to_bits()).collect(),
+        FlatSamples::U32(x) => x.to_owned(),
+        FlatSamples::F16(_) => panic!("Error: {:?} Cryptomatte ID channel {} is half float, which can't hold the ID hashes; render it as full float", path, name),
+    }
+}
@@ -0,0 +1,83 @@
+use exr::meta::MetaData;
+use exr::meta::attribute::IntegerBounds;
+use std::path::Path;
+
+
+/// Resolution of a square render, from the display window of its EXR.
+pub fn exr_resolution(path: &Path) -> u32 {
+    let meta = MetaData::read_from_file(path, false).unwrap_or_else(|e| panic!("Error: cannot read {:?} ({})", path, e));
+    let size = meta.headers[0].shared_attributes.display_window.size;
+    if size.0 != size.1 {
+        panic!("Error: {:?} is {}x{}, only square renders are supported", path, size.0, size.1);
+    }
+    size.0 as u32
+}
+
+
+/// Panics unless the image read from `path` has a `resolution` square display window.
+pub fn check_display_window(path: &Path, display: IntegerBounds, resolution: u32) {
+    if display.size.0 != resolution as usize || display.size.1 != resolution as usize {
+        panic!(
+            "Error: {:?} is {}x{} but {}x{} was expected, pass --resolution, or --base-resolution and --level, matching the renders",
+            path, display.size.0, display.size.1, resolution, resolution,
+        );
+    }
+}
+
+
+/// Places the `samples` of a channel's data window in a buffer the size of the display window.
+/// Pixels the data window doesn't cover, e.g. outside a cropped render region, are `fill`, and
+/// samples outside the display window are dropped.
+pub fn place_in_display_window<T: Copy>(samples: &[T], display: IntegerBounds, data: IntegerBounds, fill: T) -> Vec<T> {
+    if data == display {
+        return samples.to_vec();
+    }
+
+    let (width, height) = (display.size.0 as i64, display.size.1 as i64);
+    let (dx, dy) = ((data.position.0 - display.position.0) as i64, (data.position.1 - display.position.1) as i64);
+    let mut placed = vec![fill; display.size.area()];
+    if data.size.area() == 0 {
+        return placed;
+    }
+
+    // Columns of each data row that land inside the display window, none when it's entirely to
+    // the left or right of it
+    let first = (-dx).clamp(0, data.size.0 as i64);
+    let last = (width - dx).clamp(first, data.size.0 as i64);
+    for (row, samples) in samples.chunks(data.size.0).enumerate() {
+        let y = row as i64 + dy;
+        if y < 0 || y >= height || first >= last {
+            continue;
+        }
+        let start = (y * width + dx + first) as usize;
+        placed[start..start + (last - first) as usize].copy_from_slice(&samples[first as usize..last as usize]);
+    }
+    placed
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn place_in_display_window_offsets_and_clips() {
+        let display = IntegerBounds::new((0, 0), (4, 3));
+
+        // A 3x2 region at (2, 1) runs one column past the right edge
+        let data = IntegerBounds::new((2, 1), (3, 2));
+        let placed = place_in_display_window(&[1, 2, 3, 4, 5, 6], display, data, 0);
+        assert_eq!(placed, [0, 0, 0, 0, 0, 0, 1, 2, 0, 0, 4, 5]);
+
+        // Display windows needn't start at the origin, and regions can start before them
+        let display = IntegerBounds::new((-1, -1), (2, 2));
+        let data = IntegerBounds::new((-2, -1), (2, 1));
+        assert_eq!(place_in_display_window(&[7, 8], display, data, 9), [8, 9, 9, 9]);
+
+        // Regions entirely left or right of the display window leave it filled
+        let left = IntegerBounds::new((-4, 0), (2, 1));
+        let right = IntegerBounds::new((1, 0), (2, 1));
+        assert_eq!(place_in_display_window(&[7, 8], display, left, 9), [9, 9, 9, 9]);
+        assert_eq!(place_in_display_window(&[7, 8], display, right, 9), [9, 9, 9, 9]);
+    }
+}